@@ -0,0 +1,203 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Result;
+use clap::Parser;
+
+use crate::pipeline::{Pipeline, PipelineOptions};
+
+/// A self-documenting task runner for shell scripts
+///
+/// Runner options must come before the command name. Everything after the command name is passed
+/// through to the command unchanged.
+#[derive(Debug, Parser)]
+#[command(
+  name = "run",
+  version,
+  about,
+  override_usage = "run [OPTIONS] [COMMAND] [ARGS]...",
+  disable_help_subcommand = true
+)]
+pub struct Cli {
+  /// Path to the Runfile to use instead of searching for one
+  #[arg(short = 'f', long, value_name = "FILE", conflicts_with = "directory")]
+  pub file: Option<PathBuf>,
+  /// Directory to start searching for a Runfile from
+  #[arg(short = 'd', long, value_name = "DIR")]
+  pub directory: Option<PathBuf>,
+  /// Seconds a script gets to exit after a forwarded signal before it is killed
+  #[arg(long, value_name = "SECONDS")]
+  pub grace_period: Option<u64>,
+  /// Run up to N commands at once, starting dependencies as soon as their own dependencies finish
+  #[arg(short = 'j', long, value_name = "N")]
+  pub jobs: Option<usize>,
+  /// With --jobs, keep running commands that don't depend on a failed one
+  #[arg(long, requires = "jobs")]
+  pub keep_going: bool,
+  /// List available commands
+  #[arg(short = 'l', long, conflicts_with = "command")]
+  pub list: bool,
+  /// Include private commands when listing
+  #[arg(short = 'a', long, conflicts_with = "command")]
+  pub all: bool,
+  /// Rewrite the Runfile in its canonical layout
+  #[arg(long, conflicts_with = "command")]
+  pub fmt: bool,
+  /// Check the Runfile for errors and likely mistakes, or with --fmt, fail if it isn't formatted instead
+  /// of formatting it
+  #[arg(long, conflicts_with = "command")]
+  pub check: bool,
+  /// Check every command of the Runfile, not just the one being run, and fail if any has errors
+  #[arg(long, conflicts_with_all = ["command", "fmt", "check"])]
+  pub validate: bool,
+  /// With --check, print each problem as one line of JSON
+  #[arg(long, requires = "check", conflicts_with = "fmt")]
+  pub json: bool,
+  /// Print completion candidates for the last of the given words, for shell completion scripts
+  #[arg(long, hide = true)]
+  pub complete: bool,
+  /// Command to run, followed by the arguments and flags passed to it
+  #[arg(value_name = "COMMAND", trailing_var_arg = true)]
+  pub command: Vec<String>,
+}
+
+impl Cli {
+  /// Build the pipeline options described by the runner options
+  pub fn options(&self) -> PipelineOptions {
+    PipelineOptions {
+      directory: self.directory.clone(),
+      runfile: self.file.clone(),
+      grace_period: self.grace_period.map(Duration::from_secs),
+      jobs: self.jobs,
+      keep_going: self.keep_going,
+      // Completion must stay quiet, and --check and --validate report the warnings themselves
+      print_warnings: !(self.complete || self.check || self.validate),
+      // Prefix matching and the legacy grammar are turned on by the Runfile with `@set`
+      ..PipelineOptions::default()
+    }
+  }
+  /// Run the requested command, or list commands if none was given
+  pub fn execute(self) -> Result<()> {
+    let pipeline = Pipeline::with_options(self.options());
+    if self.complete {
+      // Completion must stay quiet, so a missing or broken Runfile just offers nothing
+      for candidate in pipeline.complete(&self.command).unwrap_or_default() {
+        println!("{}", candidate);
+      }
+      return Ok(());
+    }
+    if self.fmt {
+      return pipeline.format(self.check);
+    }
+    if self.check {
+      return pipeline.check(self.json);
+    }
+    if self.validate {
+      return pipeline.validate();
+    }
+    match self.command.split_first() {
+      Some((command_name, cli_args)) => pipeline.execute_command_inherit(command_name, cli_args.to_vec()),
+      None => pipeline.show_help(true, self.all),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_cli_no_command() {
+    let cli = Cli::try_parse_from(["run"]).unwrap();
+    assert!(cli.command.is_empty());
+    assert!(!cli.list);
+    assert!(cli.options().print_warnings);
+    let cli = Cli::try_parse_from(["run", "--complete", "bu"]).unwrap();
+    assert!(!cli.options().print_warnings);
+  }
+
+  #[test]
+  fn test_cli_passes_command_args_through() {
+    let cli = Cli::try_parse_from(["run", "build", "--release", "-d", "target", "--help", "-V"]).unwrap();
+    assert_eq!(
+      cli.command,
+      vec!["build", "--release", "-d", "target", "--help", "-V"]
+    );
+    assert!(cli.directory.is_none());
+  }
+
+  #[test]
+  fn test_cli_runner_options_before_command() {
+    let cli = Cli::try_parse_from(["run", "--directory", "/tmp/project", "test", "-v"]).unwrap();
+    assert_eq!(cli.directory, Some(PathBuf::from("/tmp/project")));
+    assert_eq!(cli.command, vec!["test", "-v"]);
+    assert_eq!(cli.options().directory, Some(PathBuf::from("/tmp/project")));
+  }
+
+  #[test]
+  fn test_cli_file() {
+    let cli = Cli::try_parse_from(["run", "-f", "ci.runfile", "lint"]).unwrap();
+    assert_eq!(cli.file, Some(PathBuf::from("ci.runfile")));
+    assert_eq!(cli.command, vec!["lint"]);
+    assert_eq!(cli.options().runfile, Some(PathBuf::from("ci.runfile")));
+    // --directory would only be ignored
+    assert!(Cli::try_parse_from(["run", "-f", "ci.runfile", "-d", "ci", "lint"]).is_err());
+  }
+
+  #[test]
+  fn test_cli_grace_period() {
+    let cli = Cli::try_parse_from(["run", "--grace-period", "2", "dev"]).unwrap();
+    assert_eq!(cli.options().grace_period, Some(Duration::from_secs(2)));
+    assert!(Cli::try_parse_from(["run", "--grace-period", "soon", "dev"]).is_err());
+  }
+
+  #[test]
+  fn test_cli_jobs() {
+    let cli = Cli::try_parse_from(["run", "-j", "4", "--keep-going", "verify"]).unwrap();
+    assert_eq!(cli.options().jobs, Some(4));
+    assert!(cli.options().keep_going);
+    assert!(Cli::try_parse_from(["run", "--keep-going", "verify"]).is_err());
+  }
+
+  #[test]
+  fn test_cli_list() {
+    let cli = Cli::try_parse_from(["run", "--list"]).unwrap();
+    assert!(cli.list);
+    assert!(Cli::try_parse_from(["run", "--list", "build"]).is_err());
+    let cli = Cli::try_parse_from(["run", "--list", "--all"]).unwrap();
+    assert!(cli.all);
+    assert!(Cli::try_parse_from(["run", "--all", "build"]).is_err());
+  }
+
+  #[test]
+  fn test_cli_fmt() {
+    let cli = Cli::try_parse_from(["run", "--fmt", "--check"]).unwrap();
+    assert!(cli.fmt && cli.check);
+    assert!(Cli::try_parse_from(["run", "--fmt", "build"]).is_err());
+    // On its own, --check runs the linter
+    let cli = Cli::try_parse_from(["run", "--check", "--json"]).unwrap();
+    assert!(cli.check && cli.json && !cli.fmt);
+    assert!(Cli::try_parse_from(["run", "--check", "build"]).is_err());
+    assert!(Cli::try_parse_from(["run", "--json"]).is_err());
+    assert!(Cli::try_parse_from(["run", "--fmt", "--check", "--json"]).is_err());
+  }
+
+  #[test]
+  fn test_cli_validate() {
+    let cli = Cli::try_parse_from(["run", "--validate"]).unwrap();
+    assert!(cli.validate);
+    assert!(Cli::try_parse_from(["run", "--validate", "build"]).is_err());
+    assert!(Cli::try_parse_from(["run", "--validate", "--check"]).is_err());
+  }
+
+  #[test]
+  fn test_cli_complete() {
+    let cli = Cli::try_parse_from(["run", "--complete", "--", "deploy", "--env="]).unwrap();
+    assert!(cli.complete);
+    assert_eq!(cli.command, vec!["deploy", "--env="]);
+  }
+
+  #[test]
+  fn test_cli_unknown_runner_option() {
+    assert!(Cli::try_parse_from(["run", "--bogus", "build"]).is_err());
+  }
+}
@@ -0,0 +1,396 @@
+//! The canonical layout of a Runfile, as written by `run --fmt`
+//!
+//! Formatting works on the [`SyntaxTree`], so it only changes whitespace, where parameters are declared and
+//! group separators:
+//!
+//! - Parameters are written on the command line, unless one has a description, there are comments or blank
+//!   lines between them, or the line would be longer than 80 characters. Then they're written one per line
+//!   below it, indented by two spaces, with their `# ` descriptions lined up.
+//! - Script lines stay exactly as written, except that indentation made only of spaces starts at two
+//!   spaces, keeping the indentation of those lines relative to each other. Tabs matter to `<<-` heredocs
+//!   and trailing whitespace may be inside a quoted string, so neither is touched, and scripts with a
+//!   heredoc or a quoted string over several lines aren't moved at all.
+//! - Group separators are `# ---`.
+//! - Runs of spaces in command lines and attributes become one space, and trailing whitespace goes.
+//! - Commands and group headers are separated by one blank line, and there are no other runs of blank
+//!   lines outside of scripts.
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+  lint::Quotes,
+  phases::{
+    diagnostic::Span,
+    syntax::{CommandNode, Item, Line, LineKind, SyntaxTree},
+    tokenize::TokenizePhase,
+  },
+};
+
+const INDENT: &str = "  ";
+const GROUP_SEPARATOR: &str = "# ---";
+/// Longest command line that parameters are written on
+const INLINE_WIDTH: usize = 80;
+
+/// Format a Runfile, which may still contain errors; lines that can't be read keep their content
+pub fn format(content: &str) -> Result<String> {
+  format_tree(SyntaxTree::parse(content))
+}
+
+/// Format a Runfile that's already been read, failing if it was read with the legacy grammar
+pub fn format_tree(tree: SyntaxTree) -> Result<String> {
+  if tree.legacy_grammar {
+    return Err(anyhow!(
+      "A Runfile read with the legacy grammar can't be formatted\n\nFix the lines it warns about, then remove \
+       @set legacy-grammar"
+    ));
+  }
+  let ending = tree
+    .lines()
+    .find(|line| !line.ending.is_empty())
+    .map_or("\n", |line| &line.ending)
+    .to_string();
+  let mut items = Vec::new();
+  for mut item in tree.items {
+    match &mut item {
+      Item::Line(line) => format_top_level_line(line),
+      Item::GroupHeader(lines) => {
+        lines[0].text = GROUP_SEPARATOR.to_string();
+        lines[1].text = format!("# {}", lines[1].content().trim_start_matches('#').trim());
+        lines[2].text = GROUP_SEPARATOR.to_string();
+      }
+      Item::Command(command) => format_command(command),
+    }
+    let blank = is_blank(&item);
+    let previous_blank = items.last().is_none_or(is_blank);
+    if blank && previous_blank {
+      // No blank lines at the start, and one at most between items
+      continue;
+    }
+    if matches!(item, Item::Command(_) | Item::GroupHeader(_)) && !previous_blank {
+      items.push(Item::Line(blank_line(&ending)));
+    }
+    items.push(item);
+  }
+  while items.last().is_some_and(is_blank) {
+    items.pop();
+  }
+  let mut tree = SyntaxTree {
+    items,
+    legacy_grammar: false,
+  };
+  // The file ends with a line ending, like every other line
+  if let Some(last) = last_line(&mut tree)
+    && last.ending.is_empty()
+  {
+    last.ending = ending;
+  }
+  Ok(tree.to_string())
+}
+
+fn format_top_level_line(line: &mut Line) {
+  line.text = match line.kind {
+    LineKind::Attribute => collapse_spaces(&line.text),
+    _ => line.text.trim_end().to_string(),
+  };
+}
+
+fn format_command(command: &mut CommandNode) {
+  for line in &mut command.leading {
+    format_top_level_line(line);
+  }
+  command.header.text = collapse_spaces(&command.header.text);
+  arrange_parameters(command);
+  format_parameters(&mut command.parameters);
+  format_script(&mut command.script);
+}
+
+/// Put the parameters on the command line if none has a description, there are no comments or blank lines
+/// between them, and the line is at most [`INLINE_WIDTH`] long, and otherwise one per line below it
+///
+/// A command is only rearranged if it declares the same parameters afterwards, so one that can't be read
+/// stays as it is.
+fn arrange_parameters(command: &mut CommandNode) {
+  let header = command.header.text.clone();
+  let line = header.strip_suffix(':').unwrap_or(&header).trim_end();
+  let Ok((_, inline)) = TokenizePhase::new().parse_command_line(line) else {
+    return;
+  };
+  let words: Vec<&str> = line.split_whitespace().collect();
+  let names = words[..words.len() - inline.len()].join(" ");
+  // Declarations without their colons, and their descriptions
+  let mut declarations = Vec::new();
+  let mut inline = inline.into_iter().peekable();
+  while let Some(word) = inline.next() {
+    let declaration = match inline.peek() {
+      Some(long) if word.starts_with('-') && word.ends_with(',') => format!("{} {}", word, long),
+      _ => word.to_string(),
+    };
+    if declaration.contains(' ') {
+      inline.next();
+    }
+    declarations.push((declaration, None));
+  }
+  let inline_count = declarations.len();
+  let mut separated = false;
+  for line in &command.parameters {
+    if line.kind != LineKind::Parameter {
+      separated = true;
+      continue;
+    }
+    let (declaration, description) = split_description(line.content());
+    declarations.push((
+      declaration
+        .strip_suffix(':')
+        .unwrap_or(&declaration)
+        .to_string(),
+      description,
+    ));
+  }
+  if declarations.is_empty() {
+    return;
+  }
+  let mut arranged = command.clone();
+  let inline_header = format!(
+    "{} {}:",
+    names,
+    declarations
+      .iter()
+      .map(|(declaration, _)| declaration.as_str())
+      .collect::<Vec<_>>()
+      .join(" ")
+  );
+  if !separated
+    && declarations
+      .iter()
+      .all(|(_, description)| description.is_none())
+    && inline_header.chars().count() <= INLINE_WIDTH
+  {
+    arranged.header.text = inline_header;
+    arranged.parameters.clear();
+  } else {
+    arranged.header.text = names;
+    let last = declarations.len() - 1;
+    let mut texts = declarations
+      .into_iter()
+      .enumerate()
+      .map(|(index, (declaration, description))| {
+        let colon = if index == last { ":" } else { "" };
+        match description {
+          Some(description) => format!("{INDENT}{declaration}{colon} # {description}"),
+          None => format!("{INDENT}{declaration}{colon}"),
+        }
+      });
+    let moved = texts.by_ref().take(inline_count).map(|text| Line {
+      kind: LineKind::Parameter,
+      text,
+      ending: command.header.ending.clone(),
+      span: Default::default(),
+    });
+    arranged.parameters = moved.collect();
+    for mut line in command.parameters.iter().cloned() {
+      if line.kind == LineKind::Parameter {
+        line.text = texts.next().unwrap_or_default();
+      }
+      arranged.parameters.push(line);
+    }
+  }
+  if let Some(parameters) = declared(command)
+    && declared(&arranged) == Some(parameters)
+  {
+    *command = arranged;
+  }
+}
+
+/// The arguments and flags a command declares, if it can be read on its own
+fn declared(command: &CommandNode) -> Option<String> {
+  let mut content: String = std::iter::once(&command.header)
+    .chain(&command.parameters)
+    .map(|line| format!("{}\n", line.text))
+    .collect();
+  content.push_str("  true\n");
+  let runfile = crate::parse_runfile(&content).ok()?;
+  let command = runfile.commands.first()?;
+  let mut args = command.args.clone();
+  args.iter_mut().for_each(|arg| arg.span = Span::default());
+  let mut flags = command.flags.clone();
+  flags
+    .iter_mut()
+    .for_each(|flag| flag.span = Span::default());
+  Some(format!("{:?} {:?}", args, flags))
+}
+
+/// Split a parameter line's content into its declaration and its description
+fn split_description(content: &str) -> (String, Option<String>) {
+  match content.split_once(" # ") {
+    Some((declaration, description)) => (
+      collapse_spaces(declaration),
+      Some(description.trim().to_string()),
+    ),
+    None => (collapse_spaces(content), None),
+  }
+}
+
+/// Indent the parameters by two spaces, with their descriptions lined up after the longest declaration
+fn format_parameters(parameters: &mut [Line]) {
+  let declarations: Vec<Option<(String, Option<String>)>> = parameters
+    .iter()
+    .map(|line| (line.kind == LineKind::Parameter).then(|| split_description(line.content())))
+    .collect();
+  let width = declarations
+    .iter()
+    .flatten()
+    .filter(|(_, description)| description.is_some())
+    .map(|(declaration, _)| declaration.chars().count())
+    .max()
+    .unwrap_or(0);
+  for (line, declaration) in parameters.iter_mut().zip(declarations) {
+    line.text = match declaration {
+      Some((declaration, Some(description))) => format!("{INDENT}{declaration:width$}  # {description}"),
+      Some((declaration, None)) => format!("{INDENT}{declaration}"),
+      None if line.kind == LineKind::Blank => String::new(),
+      None => format!("{INDENT}{}", line.content()),
+    };
+  }
+}
+
+/// Move the script lines indented with spaces only so the least indented of them starts at two spaces,
+/// keeping how much more some lines are indented than others
+///
+/// Scripts with a quoted string or a heredoc over several lines are left alone, since indentation in
+/// those is part of a value.
+fn format_script(script: &mut [Line]) {
+  let mut quotes = Quotes::default();
+  if script
+    .iter()
+    .any(|line| line.text.contains("<<") || quotes.continues(&line.text))
+  {
+    return;
+  }
+  let spaces_only =
+    |line: &Line| line.kind != LineKind::Blank && line.is_indented() && line.indent().bytes().all(|byte| byte == b' ');
+  let Some(base) = script
+    .iter()
+    .filter(|line| spaces_only(line))
+    .map(|line| line.indent().len())
+    .min()
+  else {
+    return;
+  };
+  for line in script.iter_mut().filter(|line| spaces_only(line)) {
+    line.text = format!("{INDENT}{}", &line.text[base..]);
+  }
+}
+
+fn collapse_spaces(text: &str) -> String {
+  text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn is_blank(item: &Item) -> bool {
+  matches!(item, Item::Line(line) if line.kind == LineKind::Blank)
+}
+
+fn blank_line(ending: &str) -> Line {
+  Line {
+    kind: LineKind::Blank,
+    text: String::new(),
+    ending: ending.to_string(),
+    span: Default::default(),
+  }
+}
+
+fn last_line(tree: &mut SyntaxTree) -> Option<&mut Line> {
+  match tree.items.last_mut()? {
+    Item::Line(line) => Some(line),
+    Item::GroupHeader(lines) => Some(&mut lines[2]),
+    Item::Command(command) => Some(
+      command
+        .script
+        .last_mut()
+        .or(command.parameters.last_mut())
+        .unwrap_or(&mut command.header),
+    ),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_format() {
+    let content = "\n\n@set   prefix-matching\n# ----------\n#   Build\n# -\nbuild\n\t--release # Optimize\n\
+      \t-t,  --target=<name>   # Target triple\n    profile:\n\tcargo build $release\n\t\t--target \"$TARGET\"   \n\n\n\
+      \techo done\n\n\n# Test\n@depends  build\ntest  ...args:\n    cargo test $args\n";
+    let expected = "@set prefix-matching\n\n# ---\n# Build\n# ---\n\nbuild\n  --release            # Optimize\n\
+      \x20 -t, --target=<name>  # Target triple\n  profile:\n\tcargo build $release\n\t\t--target \"$TARGET\"   \n\n\n\
+      \techo done\n\n# Test\n@depends build\ntest ...args:\n  cargo test $args\n";
+    assert_eq!(format(content).unwrap(), expected);
+    assert_eq!(format(expected).unwrap(), expected);
+  }
+
+  #[test]
+  fn test_format_keeps_scripts() {
+    // Tabs end a `<<-` heredoc, and spaces in a quoted string are part of it
+    let content = "greet:\n\tcat <<-EOF\n\t\thello\n\tEOF\n\techo \"a  \n  b\"\n";
+    assert_eq!(format(content).unwrap(), content);
+    // Indentation inside a string or heredoc is part of it
+    for content in [
+      "greet:\n    echo \"a\n        b\"\n",
+      "greet:\n    cat <<EOF\n      hello\n    EOF\n",
+    ] {
+      assert_eq!(format(content).unwrap(), content);
+    }
+    // Indentation made of spaces only moves, the rest of the line doesn't
+    assert_eq!(
+      format("build:\n    if true; then  \n      echo \"x  \"\n\tfi\n    done\n").unwrap(),
+      "build:\n  if true; then  \n    echo \"x  \"\n\tfi\n  done\n"
+    );
+  }
+
+  #[test]
+  fn test_format_arranges_parameters() {
+    // Without descriptions, parameters go on the command line
+    assert_eq!(
+      format("build\n  target\n  --release:\n  cargo build\n").unwrap(),
+      "build target --release:\n  cargo build\n"
+    );
+    // With one, they all go below it
+    assert_eq!(
+      format("deploy env -f, --force\n  --dry-run  # Only print\n  target:\n  ./deploy\n").unwrap(),
+      "deploy\n  env\n  -f, --force\n  --dry-run  # Only print\n  target:\n  ./deploy\n"
+    );
+    // Comments between parameters keep them below the command line, and so does a long line
+    for content in [
+      "build\n  # Mode\n  --release:\n  cargo build\n",
+      "build\n  --target=<triple>\n  --features=<list>\n  --profile=<name>\n  --jobs=<int>\n  --locked:\n  cargo build\n",
+    ] {
+      assert_eq!(format(content).unwrap(), content);
+    }
+    // Lines that aren't parameters stay where they are
+    let content = "build\n  not a parameter\n  cargo build\n";
+    assert_eq!(format(content).unwrap(), content);
+  }
+
+  #[test]
+  fn test_format_refuses_legacy_grammar() {
+    let content = "@set legacy-grammar\n\nbuild:\n\t-r\n\tcargo build\n";
+    assert!(format(content).is_err());
+  }
+
+  #[test]
+  fn test_format_separates_commands() {
+    assert_eq!(
+      format("a:\n  echo a\nb:\n  echo b").unwrap(),
+      "a:\n  echo a\n\nb:\n  echo b\n"
+    );
+  }
+
+  #[test]
+  fn test_format_keeps_line_endings() {
+    assert_eq!(
+      format("build:\r\n    cargo build\r\n").unwrap(),
+      "build:\r\n  cargo build\r\n"
+    );
+    assert_eq!(format("").unwrap(), "");
+  }
+}
@@ -1,11 +1,16 @@
+pub mod cli;
+pub mod format;
+pub mod lint;
 pub mod phases;
 pub mod pipeline;
+mod process;
+mod suggest;
 
 use std::path::PathBuf;
 
 use anyhow::Result;
 
-use crate::phases::{ParsePhase, TokenizePhase};
+use crate::phases::{ParsePhase, TokenizePhase, diagnostic::Diagnostics};
 // Re-export the main Pipeline struct for convenience
 pub use crate::pipeline::Pipeline;
 
@@ -24,12 +29,27 @@ pub fn parse_runfile(content: &str) -> Result<crate::phases::parse::Runfile> {
   Ok(runfile)
 }
 
+/// Parse a runfile content string, carrying on past problems, and return the parsed Runfile with every
+/// error and warning found in it
+///
+/// Lines with errors are left out of the Runfile, so editors can still use the rest of it.
+pub fn parse_runfile_with_diagnostics(content: &str) -> (crate::phases::parse::Runfile, Diagnostics) {
+  Pipeline::new().parse_with_diagnostics(content)
+}
+
+/// Check a runfile content string as a whole, with every command in it and not just one that is run, and
+/// return every error and warning found, for example to check a Runfile before committing it
+pub fn validate_runfile(content: &str) -> Diagnostics {
+  let (_, diagnostics) = Pipeline::new().validate_with_diagnostics(content);
+  diagnostics
+}
+
 /// Execute a command
 pub fn execute_command(args: &[String]) -> Result<()> {
   let pipeline = Pipeline::new();
   if args.is_empty() {
     // No command provided, show help
-    pipeline.show_help(true)?;
+    pipeline.show_help(true, false)?;
   } else {
     let command_name = &args[0];
     let cli_args = args[1..].to_vec();
@@ -0,0 +1,508 @@
+//! Warnings about likely mistakes in a Runfile, as reported by `run --check`
+//!
+//! Each lint has a name, shown with its warnings:
+//!
+//! - `unused-parameter`: an argument or flag whose variables the script never uses.
+//! - `undeclared-variable`: a `$variable` in a script that looks like a parameter, but isn't one and isn't
+//!   set in the script either.
+//! - `missing-description`: a public command without a description.
+//! - `empty-group`: a group without any commands.
+//! - `shadowed-name`: with prefix matching, a name that another command's name starts with, so that
+//!   prefix can never find the other command.
+//! - `variable-collision`: two parameters of a command that set the same variables, like `--a-b` and
+//!   `--a_b`.
+//!
+//! Lints expect a Runfile without errors. Variables are only looked for in scripts run by a shell, and
+//! `undeclared-variable` only in Runfiles read with the current grammar.
+
+use std::collections::HashSet;
+
+use regex::Regex;
+
+use crate::{
+  phases::{
+    diagnostic::{Diagnostic, Diagnostics, Span},
+    parse::{Command, Runfile},
+    syntax::{CommandNode, LineKind, SyntaxTree},
+  },
+  suggest,
+};
+
+/// Shells whose scripts use `$name` for variables
+const SHELLS: [&str; 6] = ["sh", "bash", "zsh", "dash", "ksh", "ash"];
+/// Lowercase variables that usually come from the environment rather than the Runfile
+const ENVIRONMENT: [&str; 5] = [
+  "http_proxy",
+  "https_proxy",
+  "ftp_proxy",
+  "all_proxy",
+  "no_proxy",
+];
+
+/// Find likely mistakes in `runfile`, read from the Runfile that `tree` was parsed from
+pub fn lint(runfile: &Runfile, tree: &SyntaxTree) -> Diagnostics {
+  let mut diagnostics = Diagnostics::new();
+  for command in &runfile.commands {
+    let parameters = parameters(command);
+    unused_parameters(command, &parameters, &mut diagnostics);
+    variable_collisions(&parameters, &mut diagnostics);
+    if !tree.legacy_grammar
+      && let Some(node) = tree
+        .commands()
+        .find(|node| node.header.span.line == command.span.line)
+    {
+      undeclared_variables(command, node, &parameters, &mut diagnostics);
+    }
+    if !command.is_private() && command.description.is_none() {
+      diagnostics.push(
+        Diagnostic::warning(
+          command.span,
+          format!("Command '{}' has no description", command.name()),
+        )
+        .with_code("missing-description"),
+      );
+    }
+  }
+  for group in &runfile.groups {
+    if !runfile
+      .commands
+      .iter()
+      .any(|command| command.group.as_ref() == Some(&group.name))
+    {
+      diagnostics.push(
+        Diagnostic::warning(
+          group.span,
+          format!("Group '{}' has no commands", group.name),
+        )
+        .with_code("empty-group"),
+      );
+    }
+  }
+  if runfile.settings.prefix_matching {
+    shadowed_names(runfile, &mut diagnostics);
+  }
+  diagnostics.sort();
+  diagnostics
+}
+
+/// An argument or flag, with the variables it sets in the script
+struct Parameter {
+  /// `argument 'target'` or `flag --release`
+  label: String,
+  /// The name as written, then in capitals if that's different, with dashes in flag names replaced by
+  /// underscores
+  variables: Vec<String>,
+  is_argument: bool,
+  is_varargs: bool,
+  span: Span,
+}
+
+fn parameters(command: &Command) -> Vec<Parameter> {
+  let arguments = command.args.iter().map(|arg| Parameter {
+    label: format!("argument '{}'", arg.name),
+    variables: variables(&arg.name),
+    is_argument: true,
+    is_varargs: arg.is_varargs,
+    span: arg.span,
+  });
+  let flags = command.flags.iter().map(|flag| Parameter {
+    label: format!("flag --{}", flag.long),
+    variables: variables(&flag.long.replace('-', "_")),
+    is_argument: false,
+    is_varargs: false,
+    span: flag.span,
+  });
+  arguments.chain(flags).collect()
+}
+
+fn variables(name: &str) -> Vec<String> {
+  let mut variables = vec![name.to_string(), name.to_uppercase()];
+  variables.dedup();
+  variables
+}
+
+/// `$name and $NAME`
+fn list(variables: &[impl AsRef<str>]) -> String {
+  let variables: Vec<String> = variables
+    .iter()
+    .map(|variable| format!("${}", variable.as_ref()))
+    .collect();
+  variables.join(" and ")
+}
+
+impl Parameter {
+  /// Whether `name` is one of the numbered variables of varargs, or their count
+  fn is_varargs_variable(&self, name: &str) -> bool {
+    self.is_varargs
+      && self
+        .variables
+        .last()
+        .is_some_and(|upper| name.starts_with(&format!("{}_", upper)))
+  }
+}
+
+fn unused_parameters(command: &Command, parameters: &[Parameter], diagnostics: &mut Diagnostics) {
+  if command.script.trim().is_empty() {
+    return;
+  }
+  let shell = is_shell(command);
+  let words: HashSet<&str> = if shell {
+    Regex::new(r"\$(?:\{[#!]?)?([A-Za-z_][A-Za-z0-9_]*)")
+      .expect("valid regex")
+      .captures_iter(&command.script)
+      .filter_map(|captures| captures.get(1))
+      .map(|name| name.as_str())
+      .collect()
+  } else {
+    // Other languages read the environment in their own way, so any mention of the name counts
+    command
+      .script
+      .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+      .collect()
+  };
+  // `$1`, `"$@"` and the like use every argument
+  let positional = shell
+    && Regex::new(r"\$\{?[0-9@*#]")
+      .expect("valid regex")
+      .is_match(&command.script);
+  for parameter in parameters {
+    let used = parameter
+      .variables
+      .iter()
+      .any(|variable| words.contains(variable.as_str()))
+      || (parameter.is_argument && positional)
+      || words.iter().any(|word| parameter.is_varargs_variable(word));
+    if !used {
+      diagnostics.push(
+        Diagnostic::warning(
+          parameter.span,
+          format!(
+            "The script never uses {}, which sets {}",
+            parameter.label,
+            list(&parameter.variables)
+          ),
+        )
+        .with_code("unused-parameter"),
+      );
+    }
+  }
+}
+
+/// Warn about `$name`s that look like a parameter of the command: lowercase ones that aren't set in the
+/// script, and uppercase ones close to a parameter's name
+fn undeclared_variables(
+  command: &Command,
+  node: &CommandNode,
+  parameters: &[Parameter],
+  diagnostics: &mut Diagnostics,
+) {
+  if !is_shell(command) {
+    return;
+  }
+  let declared: Vec<&str> = parameters
+    .iter()
+    .flat_map(|parameter| &parameter.variables)
+    .map(String::as_str)
+    .collect();
+  let assigned = assigned_variables(&command.script);
+  let mut reported = HashSet::new();
+  let mut quotes = Quotes::default();
+  for line in node
+    .script
+    .iter()
+    .filter(|line| line.kind == LineKind::Script)
+  {
+    for (start, end) in quotes.variables(&line.text) {
+      let name = &line.text[start..end];
+      if declared.contains(&name)
+        || parameters
+          .iter()
+          .any(|parameter| parameter.is_varargs_variable(name))
+        || assigned.contains(name)
+        || ENVIRONMENT.contains(&name)
+        || !reported.insert(name)
+      {
+        continue;
+      }
+      let suggestion = suggest::closest(name, declared.iter().copied());
+      let lowercase = name.chars().any(|c| c.is_ascii_lowercase());
+      if !lowercase && suggestion.is_none() {
+        // Capitals are usually the environment's
+        continue;
+      }
+      let mut message = format!(
+        "${} is not an argument or flag of '{}', and the script doesn't set it",
+        name,
+        command.name()
+      );
+      if let Some(suggestion) = suggestion {
+        message.push_str(&format!(". Did you mean ${}?", suggestion));
+      }
+      let span = Span::in_line(
+        line.span.line,
+        line.span.start,
+        &line.text,
+        &line.text[start..end],
+      );
+      diagnostics.push(Diagnostic::warning(span, message).with_code("undeclared-variable"));
+    }
+  }
+}
+
+/// Names the script gives a value to, by assignment, as a loop variable, or with `read`, `local` and
+/// the like
+fn assigned_variables(script: &str) -> HashSet<&str> {
+  let name = r"[A-Za-z_][A-Za-z0-9_]*";
+  let mut assigned = HashSet::new();
+  let patterns = [
+    format!(r"(?:^|[\s;&|(])({name})(?:\[[^\]]*\])?\+?="),
+    format!(r"\b(?:for|select)\s+({name})"),
+  ];
+  for pattern in patterns {
+    let regex = Regex::new(&pattern).expect("valid regex");
+    assigned.extend(
+      regex
+        .captures_iter(script)
+        .filter_map(|captures| captures.get(1))
+        .map(|name| name.as_str()),
+    );
+  }
+  let builtins =
+    Regex::new(r"\b(?:read|local|declare|typeset|export|readonly|getopts\s+\S+)((?:[ \t]+-?[A-Za-z_]\w*)+)")
+      .expect("valid regex");
+  for captures in builtins.captures_iter(script) {
+    let names = captures.get(1).map_or("", |names| names.as_str());
+    assigned.extend(
+      names
+        .split_whitespace()
+        .filter(|word| !word.starts_with('-')),
+    );
+  }
+  assigned
+}
+
+/// Where a script is in single or double quotes, carried from one line to the next
+#[derive(Default)]
+pub(crate) struct Quotes {
+  single: bool,
+  double: bool,
+}
+
+impl Quotes {
+  /// Whether a quoted string is still open at the end of `line`, so it goes on in the next one
+  pub(crate) fn continues(&mut self, line: &str) -> bool {
+    self.variables(line);
+    self.single || self.double
+  }
+  /// Byte ranges of the names of the `$name` and `${name}` variables in `line` that the shell expands,
+  /// leaving out those in single quotes, escaped with `\`, or in comments
+  fn variables(&mut self, line: &str) -> Vec<(usize, usize)> {
+    let mut variables = Vec::new();
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+      match bytes[i] {
+        b'\'' if !self.double => self.single = !self.single,
+        _ if self.single => {}
+        b'\\' => i += 1,
+        b'"' => self.double = !self.double,
+        b'#' if !self.double && (i == 0 || bytes[i - 1].is_ascii_whitespace()) => break,
+        b'$' => {
+          let start = i + 1 + usize::from(bytes.get(i + 1) == Some(&b'{'));
+          let length = bytes[start.min(bytes.len())..]
+            .iter()
+            .enumerate()
+            .take_while(|&(index, &byte)| {
+              byte == b'_' || byte.is_ascii_alphabetic() || (index > 0 && byte.is_ascii_digit())
+            })
+            .count();
+          if length > 0 {
+            variables.push((start, start + length));
+            i = start + length - 1;
+          }
+        }
+        _ => {}
+      }
+      i += 1;
+    }
+    variables
+  }
+}
+
+fn shadowed_names(runfile: &Runfile, diagnostics: &mut Diagnostics) {
+  for command in &runfile.commands {
+    for name in &command.names {
+      // Prefix matching only finds public commands
+      for other in runfile
+        .commands
+        .iter()
+        .filter(|other| !other.is_private() && !std::ptr::eq(*other, command))
+      {
+        if let Some(longer) = other
+          .names
+          .iter()
+          .find(|other_name| other_name.len() > name.len() && other_name.starts_with(name.as_str()))
+        {
+          diagnostics.push(
+            Diagnostic::warning(
+              command.span,
+              format!(
+                "`run {}` always runs '{}', never '{}', even though '{}' starts with '{}'",
+                name,
+                command.name(),
+                other.name(),
+                longer,
+                name
+              ),
+            )
+            .with_code("shadowed-name"),
+          );
+        }
+      }
+    }
+  }
+}
+
+fn variable_collisions(parameters: &[Parameter], diagnostics: &mut Diagnostics) {
+  for (index, parameter) in parameters.iter().enumerate() {
+    let Some(earlier) = parameters[..index].iter().find(|earlier| {
+      earlier
+        .variables
+        .iter()
+        .any(|variable| parameter.variables.contains(variable))
+    }) else {
+      continue;
+    };
+    let shared: Vec<&String> = parameter
+      .variables
+      .iter()
+      .filter(|variable| earlier.variables.contains(variable))
+      .collect();
+    let mut label = parameter.label.clone();
+    label[..1].make_ascii_uppercase();
+    diagnostics.push(
+      Diagnostic::warning(
+        parameter.span,
+        format!(
+          "{} sets {}, like {}, so one overwrites the other",
+          label,
+          list(&shared),
+          earlier.label
+        ),
+      )
+      .with_code("variable-collision"),
+    );
+  }
+}
+
+/// Whether the command's script runs in a shell, rather than a language named by its `#!` line
+fn is_shell(command: &Command) -> bool {
+  let mut words = command
+    .shebang
+    .trim_start_matches("#!")
+    .split_whitespace()
+    .map(|word| word.rsplit('/').next().unwrap_or(word));
+  let program = match words.next() {
+    Some("env") => words.find(|word| !word.starts_with('-')),
+    program => program,
+  };
+  program.is_none_or(|program| SHELLS.contains(&program))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parse_runfile;
+
+  fn lints(content: &str) -> Vec<(&'static str, String)> {
+    let runfile = parse_runfile(content).unwrap();
+    lint(&runfile, &SyntaxTree::parse(content))
+      .into_iter()
+      .map(|diagnostic| (diagnostic.code.unwrap(), diagnostic.message))
+      .collect()
+  }
+
+  #[test]
+  fn test_unused_parameters() {
+    let content = "# Build\nbuild target mode?\n  -r, --release\n  --per-crate:\n  cargo build $release --bin \"${TARGET}\"\n  echo $PER_CRATE";
+    assert_eq!(
+      lints(content),
+      vec![(
+        "unused-parameter",
+        "The script never uses argument 'mode', which sets $mode and $MODE".to_string()
+      )]
+    );
+    // Positional parameters use every argument, and other languages just mention the name
+    assert!(lints("# Test\ntest target ...args:\n  cargo test \"$@\"").is_empty());
+    assert!(lints("# Test\ntest ...args:\n  echo $ARGS_COUNT").is_empty());
+    assert!(lints("# Greet\ngreet name:\n  #!/usr/bin/env python3\n  print(os.environ['NAME'])").is_empty());
+  }
+
+  #[test]
+  fn test_undeclared_variables() {
+    let content = "# Build\nbuild --release:\n  cargo build $relase\n  for file in *.rs; do echo \"$file\"; done\n  \
+      read -r answer\n  count=1\n  echo $answer $count $HOME $RELAESE '$quoted' \\$escaped # $comment\n  echo $other $other";
+    let lints = lints(content);
+    let messages: Vec<&str> = lints
+      .iter()
+      .filter(|(code, _)| *code == "undeclared-variable")
+      .map(|(_, message)| message.as_str())
+      .collect();
+    assert_eq!(
+      messages,
+      vec![
+        "$relase is not an argument or flag of 'build', and the script doesn't set it. Did you mean $release?",
+        "$RELAESE is not an argument or flag of 'build', and the script doesn't set it. Did you mean $RELEASE?",
+        "$other is not an argument or flag of 'build', and the script doesn't set it",
+      ]
+    );
+  }
+
+  #[test]
+  fn test_undeclared_variable_span() {
+    let content = "# Build\nbuild:\n  echo \"${name}\"\n";
+    let runfile = parse_runfile(content).unwrap();
+    let diagnostics = lint(&runfile, &SyntaxTree::parse(content));
+    let span = diagnostics.iter().next().unwrap().span;
+    assert_eq!((span.line, span.column), (3, 11));
+    assert_eq!(&content[span.start..span.end], "name");
+  }
+
+  #[test]
+  fn test_descriptions_groups_and_names() {
+    let content = "@set prefix-matching\n# ---\n# Empty\n# ---\n# ---\n# Build\n# ---\nb, build:\n  cargo build\n\n\
+      # Bench\nbench:\n  cargo bench\n\n@private\nsetup:\n  ./setup.sh";
+    assert_eq!(
+      lints(content),
+      vec![
+        ("empty-group", "Group 'Empty' has no commands".to_string()),
+        (
+          "missing-description",
+          "Command 'build' has no description".to_string()
+        ),
+        (
+          "shadowed-name",
+          "`run b` always runs 'build', never 'bench', even though 'bench' starts with 'b'".to_string()
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_variable_collisions() {
+    let content = "# Build\nbuild release\n  --a-b\n  --a_b\n  --release:\n  echo $a_b $release";
+    assert_eq!(
+      lints(content),
+      vec![
+        (
+          "variable-collision",
+          "Flag --a_b sets $a_b and $A_B, like flag --a-b, so one overwrites the other".to_string()
+        ),
+        (
+          "variable-collision",
+          "Flag --release sets $release and $RELEASE, like argument 'release', so one overwrites the other".to_string()
+        ),
+      ]
+    );
+  }
+}
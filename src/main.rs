@@ -1,9 +1,26 @@
-use anyhow::Result;
-use run::execute_command;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
-  let args: Vec<String> = std::env::args().collect();
-  let command_args = args[1..].to_vec();
-  execute_command(&command_args)?;
-  Ok(())
+use clap::Parser;
+use run::{
+  cli::Cli,
+  phases::{diagnostic::Report, run::ScriptError},
+};
+
+fn main() -> ExitCode {
+  match Cli::parse().execute() {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(err) => {
+      // The script reports its own errors, so only its exit status is propagated
+      if let Some(failure) = err.downcast_ref::<ScriptError>() {
+        return ExitCode::from(failure.exit_code() as u8);
+      }
+      // Errors in the Runfile are already labelled with where they are
+      if let Some(report) = err.downcast_ref::<Report>() {
+        eprint!("{}", report);
+        return ExitCode::FAILURE;
+      }
+      eprintln!("Error: {:#}", err);
+      ExitCode::FAILURE
+    }
+  }
 }
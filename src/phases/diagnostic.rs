@@ -0,0 +1,344 @@
+//! Locations in a Runfile and the errors and warnings that point at them
+//!
+//! Tokens and syntax nodes carry a [`Span`]. Problems at a particular place in the Runfile are
+//! [`Diagnostic`]s, which display as just their message so they read well on their own, and render with
+//! the file's path and an excerpt of its source when a [`Report`] is printed, or as one line of JSON for
+//! tools. Phases that keep going past problems collect them in [`Diagnostics`].
+
+use std::{error::Error, fmt};
+
+/// Where a token or syntax node is in the Runfile
+///
+/// Lines and columns start at 1, with columns counted in characters. `start..end` is the byte range in
+/// the Runfile's content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+  pub line: usize,
+  pub column: usize,
+  pub start: usize,
+  pub end: usize,
+}
+
+impl Span {
+  /// Span of `part`, which must be a slice of `line`, the line numbered `number` starting at byte
+  /// `line_start` of the content
+  pub fn in_line(number: usize, line_start: usize, line: &str, part: &str) -> Self {
+    let offset = offset_in(line, part);
+    Self {
+      line: number,
+      column: line[..offset].chars().count() + 1,
+      start: line_start + offset,
+      end: line_start + offset + part.len(),
+    }
+  }
+}
+
+/// Byte offset of `inner`, a slice of `outer`, from the start of `outer`
+fn offset_in(outer: &str, inner: &str) -> usize {
+  let offset = (inner.as_ptr() as usize).wrapping_sub(outer.as_ptr() as usize);
+  debug_assert!(
+    offset + inner.len() <= outer.len(),
+    "not a slice of the line"
+  );
+  offset.min(outer.len())
+}
+
+/// A token or node together with its span
+///
+/// Compares equal to a bare node when the nodes are equal, whatever the span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+  pub node: T,
+  pub span: Span,
+}
+
+impl<T: PartialEq> PartialEq<T> for Spanned<T> {
+  fn eq(&self, other: &T) -> bool {
+    self.node == *other
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+  /// The Runfile can't be used as written
+  Error,
+  /// The Runfile works, but probably not as intended
+  Warning,
+}
+
+impl fmt::Display for Severity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Severity::Error => write!(f, "error"),
+      Severity::Warning => write!(f, "warning"),
+    }
+  }
+}
+
+/// An error or warning at a location in the Runfile
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub span: Span,
+  pub message: String,
+  /// Name of the lint that found the problem, like `unused-parameter`
+  pub code: Option<&'static str>,
+}
+
+impl Diagnostic {
+  pub fn error(span: Span, message: impl Into<String>) -> Self {
+    Self {
+      severity: Severity::Error,
+      span,
+      message: message.into(),
+      code: None,
+    }
+  }
+  pub fn warning(span: Span, message: impl Into<String>) -> Self {
+    Self {
+      severity: Severity::Warning,
+      span,
+      message: message.into(),
+      code: None,
+    }
+  }
+  pub fn with_code(self, code: &'static str) -> Self {
+    Self {
+      code: Some(code),
+      ..self
+    }
+  }
+  /// Render rustc-style: `path:line:column: error: message`, or `warning[code]` for a lint, then the
+  /// source line with a caret under the span
+  pub fn render(&self, path: &str, source: &str) -> String {
+    let Span { line, column, .. } = self.span;
+    let code = self
+      .code
+      .map(|code| format!("[{}]", code))
+      .unwrap_or_default();
+    let mut rendered = format!(
+      "{}:{}:{}: {}{}: {}\n",
+      path, line, column, self.severity, code, self.message
+    );
+    let Some(text) = source.lines().nth(line.saturating_sub(1)) else {
+      return rendered;
+    };
+    // Underline the span up to the end of its first line, with at least one caret
+    let width = source
+      .get(self.span.start..self.span.end)
+      .and_then(|spanned| spanned.lines().next())
+      .map_or(0, |spanned| spanned.chars().count())
+      .max(1);
+    let gutter = " ".repeat(line.to_string().len());
+    rendered.push_str(&format!("{} |\n", gutter));
+    rendered.push_str(&format!("{} | {}\n", line, text));
+    rendered.push_str(&format!(
+      "{} | {}{}\n",
+      gutter,
+      " ".repeat(column.saturating_sub(1)),
+      "^".repeat(width)
+    ));
+    rendered
+  }
+  /// One line of JSON with the path, where the span starts and its byte range, the severity, the lint's
+  /// name or `null`, and the message
+  pub fn to_json(&self, path: &str) -> String {
+    format!(
+      r#"{{"path":{},"line":{},"column":{},"start":{},"end":{},"severity":"{}","code":{},"message":{}}}"#,
+      json_string(path),
+      self.span.line,
+      self.span.column,
+      self.span.start,
+      self.span.end,
+      self.severity,
+      self.code.map_or("null".to_string(), json_string),
+      json_string(&self.message)
+    )
+  }
+}
+
+/// `text` as a JSON string literal
+fn json_string(text: &str) -> String {
+  let mut json = String::from('"');
+  for c in text.chars() {
+    match c {
+      '"' => json.push_str("\\\""),
+      '\\' => json.push_str("\\\\"),
+      '\n' => json.push_str("\\n"),
+      '\r' => json.push_str("\\r"),
+      '\t' => json.push_str("\\t"),
+      c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+      c => json.push(c),
+    }
+  }
+  json.push('"');
+  json
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.message)
+  }
+}
+
+impl Error for Diagnostic {}
+
+/// Errors and warnings found in a Runfile
+///
+/// As an error, it displays the message of each diagnostic on its own line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnostics {
+  diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+  pub fn new() -> Self {
+    Self::default()
+  }
+  pub fn push(&mut self, diagnostic: Diagnostic) {
+    self.diagnostics.push(diagnostic);
+  }
+  pub fn extend(&mut self, other: Diagnostics) {
+    self.diagnostics.extend(other.diagnostics);
+  }
+  pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+    self.diagnostics.iter()
+  }
+  pub fn len(&self) -> usize {
+    self.diagnostics.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.diagnostics.is_empty()
+  }
+  pub fn has_errors(&self) -> bool {
+    self
+      .diagnostics
+      .iter()
+      .any(|diagnostic| diagnostic.severity == Severity::Error)
+  }
+  /// Order the diagnostics by where they are in the Runfile, keeping the order of those at the same place
+  pub fn sort(&mut self) {
+    self
+      .diagnostics
+      .sort_by_key(|diagnostic| diagnostic.span.start);
+  }
+  /// Fail with all of the diagnostics if any of them is an error
+  pub fn into_result(self) -> Result<(), Self> {
+    if self.has_errors() { Err(self) } else { Ok(()) }
+  }
+}
+
+impl From<Diagnostic> for Diagnostics {
+  fn from(diagnostic: Diagnostic) -> Self {
+    Self {
+      diagnostics: vec![diagnostic],
+    }
+  }
+}
+
+impl IntoIterator for Diagnostics {
+  type Item = Diagnostic;
+  type IntoIter = std::vec::IntoIter<Diagnostic>;
+  fn into_iter(self) -> Self::IntoIter {
+    self.diagnostics.into_iter()
+  }
+}
+
+impl fmt::Display for Diagnostics {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (index, diagnostic) in self.diagnostics.iter().enumerate() {
+      if index > 0 {
+        writeln!(f)?;
+      }
+      write!(f, "{}", diagnostic)?;
+    }
+    Ok(())
+  }
+}
+
+impl Error for Diagnostics {}
+
+/// Diagnostics for one Runfile, displayed with its path and excerpts of its source
+#[derive(Debug)]
+pub struct Report {
+  /// Path of the Runfile as shown in messages
+  pub path: String,
+  pub source: String,
+  pub diagnostics: Diagnostics,
+}
+
+impl fmt::Display for Report {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (index, diagnostic) in self.diagnostics.iter().enumerate() {
+      if index > 0 {
+        writeln!(f)?;
+      }
+      write!(f, "{}", diagnostic.render(&self.path, &self.source))?;
+    }
+    Ok(())
+  }
+}
+
+impl Error for Report {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_span_in_line() {
+    let content = "build:\n  --rélease\n";
+    let line = &content[7..19];
+    assert_eq!(line, "  --rélease");
+    let span = Span::in_line(2, 7, line, line.trim());
+    assert_eq!(
+      span,
+      Span {
+        line: 2,
+        column: 3,
+        start: 9,
+        end: 19
+      }
+    );
+    assert_eq!(&content[span.start..span.end], "--rélease");
+  }
+
+  #[test]
+  fn test_render() {
+    let source = "build:\n  cargo build\n\ntest --fast --fast:\n  cargo test\n";
+    let diagnostic = Diagnostic::error(
+      Span {
+        line: 4,
+        column: 13,
+        start: 34,
+        end: 40,
+      },
+      "Duplicate flag name: fast",
+    );
+    assert_eq!(diagnostic.to_string(), "Duplicate flag name: fast");
+    assert_eq!(
+      diagnostic.render("Runfile", source),
+      "Runfile:4:13: error: Duplicate flag name: fast\n  |\n4 | test --fast --fast:\n  |             ^^^^^^\n"
+    );
+  }
+
+  #[test]
+  fn test_to_json() {
+    let span = Span {
+      line: 2,
+      column: 3,
+      start: 9,
+      end: 14,
+    };
+    let diagnostic = Diagnostic::warning(span, "Flag \"--fast\" is\tnever used").with_code("unused-parameter");
+    assert_eq!(
+      diagnostic.to_json("dir\\Runfile"),
+      r#"{"path":"dir\\Runfile","line":2,"column":3,"start":9,"end":14,"severity":"warning","code":"unused-parameter","message":"Flag \"--fast\" is\tnever used"}"#
+    );
+    assert!(
+      Diagnostic::error(span, "x")
+        .to_json("Runfile")
+        .contains(r#""code":null"#)
+    );
+  }
+}
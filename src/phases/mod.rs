@@ -1,6 +1,8 @@
+pub mod diagnostic;
 pub mod parse;
 pub mod resolve;
 pub mod run;
+pub mod syntax;
 pub mod tokenize;
 
 pub use parse::ParsePhase;
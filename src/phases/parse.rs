@@ -1,19 +1,35 @@
 use std::io::Write;
 
 use ansi_term::Colour;
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 
-use super::tokenize::Token;
+use super::{
+  diagnostic::{Diagnostic, Diagnostics, Span, Spanned},
+  run::{validate_choice, validate_type},
+  tokenize::Token,
+};
 
 #[derive(Debug, Clone)]
 pub struct Runfile {
   pub groups: Vec<Group>,
   pub commands: Vec<Command>,
+  pub settings: Settings,
+}
+
+/// File-wide settings, each turned on by a `@set <name>` line
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+  /// Find a command from an unambiguous prefix of one of its names, set by `@set prefix-matching`
+  pub prefix_matching: bool,
+  /// Read the Runfile with the legacy grammar, set by `@set legacy-grammar`; the tokenizer has already
+  /// acted on it
+  pub legacy_grammar: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct Group {
   pub name: String,
+  pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +41,13 @@ pub struct Command {
   pub flags: Vec<Flag>,
   pub script: String,
   pub shebang: String,
+  pub passthrough: bool,
+  /// Commands to run before this one, by name or alias, each with the span of its `@depends` line
+  pub depends: Vec<Spanned<String>>,
+  /// Hidden from help and completion, set by `@private`; see [`Command::is_private`]
+  pub private: bool,
+  /// The command line, from the first name to the colon
+  pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -32,7 +55,10 @@ pub struct Argument {
   pub name: String,
   pub optional: bool,
   pub is_varargs: bool,
+  pub type_hint: Option<String>,
+  pub default: Option<String>,
   pub description: Option<String>,
+  pub span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -41,7 +67,51 @@ pub struct Flag {
   pub long: String,
   pub takes_value: bool,
   pub type_hint: Option<String>,
+  pub default: Option<String>,
   pub description: Option<String>,
+  pub span: Span,
+}
+
+/// Built-in type of an argument or flag value, interpreted from its type hint
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueType {
+  /// `<int>`: a whole number
+  Int,
+  /// `<float>`: any number
+  Float,
+  /// `<bool>`: `true`, `false`, `yes`, `no`, `1` or `0`
+  Bool,
+  /// `<port>`: a TCP/UDP port number from 1 to 65535
+  Port,
+  /// `<path>`: any non-empty path
+  Path,
+  /// `<file>`: an existing file
+  File,
+  /// `<dir>`: an existing directory
+  Dir,
+  /// `<url>`: an absolute URL such as `https://example.com`
+  Url,
+  /// `<regex:PATTERN>`: a value the whole of which matches the pattern
+  Regex(String),
+}
+
+impl ValueType {
+  /// Interpret a type hint, returning `None` for hints that are not built-in types
+  pub fn from_hint(hint: &str) -> Option<Self> {
+    match hint {
+      "int" => Some(Self::Int),
+      "float" => Some(Self::Float),
+      "bool" => Some(Self::Bool),
+      "port" => Some(Self::Port),
+      "path" => Some(Self::Path),
+      "file" => Some(Self::File),
+      "dir" => Some(Self::Dir),
+      "url" => Some(Self::Url),
+      _ => hint
+        .strip_prefix("regex:")
+        .map(|pattern| Self::Regex(pattern.to_string())),
+    }
+  }
 }
 
 #[derive(Default)]
@@ -51,13 +121,31 @@ impl ParsePhase {
   pub fn new() -> Self {
     Self
   }
-  pub fn parse(&self, tokens: Vec<Token>) -> Result<Runfile> {
+  /// Parse the tokens, failing with every error found in them
+  pub fn parse(&self, tokens: Vec<Spanned<Token>>) -> Result<Runfile> {
+    let (runfile, diagnostics) = self.parse_with_diagnostics(tokens);
+    diagnostics.into_result()?;
+    Ok(runfile)
+  }
+  /// Parse the tokens, reporting problems instead of stopping at them
+  ///
+  /// Misplaced and invalid attributes are left out of the Runfile.
+  pub fn parse_with_diagnostics(&self, tokens: Vec<Spanned<Token>>) -> (Runfile, Diagnostics) {
+    let mut diagnostics = Diagnostics::new();
     let mut groups = Vec::new();
     let mut commands = Vec::new();
     let mut current_group: Option<String> = None;
     let mut current_command: Option<Command> = None;
     let mut in_script = false;
-    for token in tokens {
+    let mut settings = Settings::default();
+    // Attributes apply to the command defined directly below them
+    let mut attributes: Vec<(String, Vec<String>, Span)> = Vec::new();
+    for Spanned { node: token, span } in tokens {
+      if !matches!(token, Token::Attribute { .. } | Token::CommandName { .. }) {
+        for (attribute, _, attribute_span) in attributes.drain(..) {
+          diagnostics.push(misplaced_attribute(&attribute, attribute_span));
+        }
+      }
       match token {
         Token::GroupHeader { name } => {
           // Save any current command
@@ -65,7 +153,7 @@ impl ParsePhase {
             commands.push(cmd);
           }
           current_group = Some(name.clone());
-          groups.push(Group { name });
+          groups.push(Group { name, span });
           in_script = false;
         }
         Token::CommandName {
@@ -81,24 +169,33 @@ impl ParsePhase {
           // Convert inline args and flags to proper structures
           let args: Vec<Argument> = inline_args
             .into_iter()
-            .map(|(name, optional, is_varargs)| Argument {
-              name,
-              optional,
-              is_varargs,
-              description: None,
-            })
+            .map(
+              |(name, optional, is_varargs, type_hint, default, span)| Argument {
+                name,
+                optional,
+                is_varargs,
+                type_hint,
+                default,
+                description: None,
+                span,
+              },
+            )
             .collect();
           let flags: Vec<Flag> = inline_flags
             .into_iter()
-            .map(|(long, short, takes_value, type_hint)| Flag {
-              short,
-              long,
-              takes_value,
-              type_hint,
-              description: None,
-            })
+            .map(
+              |(long, short, takes_value, type_hint, default, span)| Flag {
+                short,
+                long,
+                takes_value,
+                type_hint,
+                default,
+                description: None,
+                span,
+              },
+            )
             .collect();
-          current_command = Some(Command {
+          let mut command = Command {
             names: name.clone(),
             description: comment,
             group: current_group.clone(),
@@ -106,13 +203,39 @@ impl ParsePhase {
             flags,
             script: String::new(),
             shebang: "#!/bin/sh".to_string(),
-          });
+            passthrough: false,
+            depends: Vec::new(),
+            private: false,
+            span,
+          };
+          for (attribute, attribute_args, attribute_span) in attributes.drain(..) {
+            if let Err(error) = self.apply_attribute(&mut command, &attribute, attribute_args, attribute_span) {
+              diagnostics.push(Diagnostic::error(attribute_span, error.to_string()));
+            }
+          }
+          current_command = Some(command);
           in_script = false;
         }
+        Token::Attribute { name, args } => {
+          // Save any current command
+          if let Some(cmd) = current_command.take() {
+            commands.push(cmd);
+          }
+          // `@set` applies to the whole file rather than the command below it
+          if name == "set" {
+            if let Err(error) = self.apply_setting(&mut settings, args) {
+              diagnostics.push(Diagnostic::error(span, error.to_string()));
+            }
+          } else {
+            attributes.push((name, args, span));
+          }
+        }
         Token::Argument {
           name,
           optional,
           is_varargs,
+          type_hint,
+          default,
           comment,
         } => {
           if let Some(ref mut cmd) = current_command {
@@ -121,7 +244,10 @@ impl ParsePhase {
                 name,
                 optional,
                 is_varargs,
+                type_hint,
+                default,
                 description: comment,
+                span,
               });
             } else {
               // This is part of the script, not an argument definition
@@ -137,6 +263,7 @@ impl ParsePhase {
           short,
           takes_value,
           type_hint,
+          default,
           comment,
         } => {
           if let Some(ref mut cmd) = current_command {
@@ -146,7 +273,9 @@ impl ParsePhase {
                 long: long_name,
                 takes_value,
                 type_hint,
+                default,
                 description: comment,
+                span,
               });
             } else {
               // This is part of the script, not a flag definition
@@ -195,6 +324,9 @@ impl ParsePhase {
         }
       }
     }
+    for (attribute, _, attribute_span) in attributes {
+      diagnostics.push(misplaced_attribute(&attribute, attribute_span));
+    }
     // Save the last command
     if let Some(cmd) = current_command {
       commands.push(cmd);
@@ -204,19 +336,454 @@ impl ParsePhase {
       .into_iter()
       .filter(|group| !group.name.is_empty())
       .collect();
-    Ok(Runfile { groups, commands })
+    let runfile = Runfile {
+      groups,
+      commands,
+      settings,
+    };
+    (runfile, diagnostics)
+  }
+  fn apply_attribute(&self, command: &mut Command, attribute: &str, args: Vec<String>, span: Span) -> Result<()> {
+    match attribute {
+      "passthrough" => {
+        if !args.is_empty() {
+          return Err(anyhow!("Attribute @passthrough takes no arguments"));
+        }
+        command.passthrough = true;
+      }
+      "private" => {
+        if !args.is_empty() {
+          return Err(anyhow!("Attribute @private takes no arguments"));
+        }
+        command.private = true;
+      }
+      "depends" => {
+        if args.is_empty() {
+          return Err(anyhow!("Attribute @depends requires at least one command"));
+        }
+        command
+          .depends
+          .extend(args.into_iter().map(|node| Spanned { node, span }));
+      }
+      _ => return Err(anyhow!("Unknown attribute: @{}", attribute)),
+    }
+    Ok(())
+  }
+  fn apply_setting(&self, settings: &mut Settings, args: Vec<String>) -> Result<()> {
+    let [setting] = args.as_slice() else {
+      return Err(anyhow!("Attribute @set takes exactly one setting"));
+    };
+    match setting.as_str() {
+      "prefix-matching" => settings.prefix_matching = true,
+      "legacy-grammar" => settings.legacy_grammar = true,
+      _ => return Err(anyhow!("Unknown setting: {}", setting)),
+    }
+    Ok(())
+  }
+}
+
+impl Command {
+  /// Primary name of the command: the last of its names, after any aliases
+  pub fn name(&self) -> &str {
+    self.names.last().map(String::as_str).unwrap_or_default()
+  }
+  /// Whether the command is left out of help and completion, by `@private` or a name starting with `_`
+  ///
+  /// Private commands can still be run directly and used as dependencies.
+  pub fn is_private(&self) -> bool {
+    self.private || self.name().starts_with('_')
+  }
+  /// Whether `cli_args` ask for the command's help with `--help` or `-h`, before any `--`
+  ///
+  /// A command that declares a `--help` or `-h` flag of its own receives that flag instead.
+  pub fn wants_help(&self, cli_args: &[String]) -> bool {
+    let help_flags = self.help_flags();
+    cli_args
+      .iter()
+      .take_while(|arg| *arg != "--")
+      .any(|arg| help_flags.contains(&arg.as_str()))
+  }
+  /// Help flags the command doesn't declare itself, so they show its help
+  fn help_flags(&self) -> Vec<&'static str> {
+    let mut help_flags = Vec::new();
+    if !self.flags.iter().any(|flag| flag.short == Some('h')) {
+      help_flags.push("-h");
+    }
+    if !self.flags.iter().any(|flag| flag.long == "help") {
+      help_flags.push("--help");
+    }
+    help_flags
+  }
+  /// Usage synopsis, like `Usage: run build [-r|--release] <target> [args...]`
+  pub fn usage(&self) -> String {
+    let mut usage = format!("Usage: run {}", self.name());
+    for flag in &self.flags {
+      let names = match flag.short {
+        Some(short) => format!("-{}|--{}", short, flag.long),
+        None => format!("--{}", flag.long),
+      };
+      match flag.value_placeholder() {
+        Some(placeholder) => usage.push_str(&format!(" [{} {}]", names, placeholder)),
+        None => usage.push_str(&format!(" [{}]", names)),
+      }
+    }
+    for arg in &self.args {
+      usage.push_str(&format!(" {}", arg.synopsis()));
+    }
+    usage
+  }
+  /// Detailed help for this command, as shown by `run help <command>` and `run <command> --help`
+  pub fn generate_help(&self, colors: bool) -> String {
+    let mut output = Vec::new();
+    self.generate_help_to_buffer(colors, &mut output);
+    String::from_utf8(output).unwrap_or_default()
+  }
+  /// Generate the command's help to a buffer
+  ///
+  /// The usage line comes first, then the full description, aliases, arguments, options and
+  /// dependencies, each only when there are any. Argument and option descriptions align to a single
+  /// column, two spaces after the widest of them.
+  fn generate_help_to_buffer(&self, colors: bool, output: &mut dyn Write) {
+    let heading = |text: &str| -> String {
+      if colors {
+        Colour::White.bold().paint(text).to_string()
+      } else {
+        text.to_string()
+      }
+    };
+    let usage = self.usage();
+    let synopsis = usage.strip_prefix("Usage: ").unwrap_or(&usage);
+    writeln!(output, "{} {}", heading("Usage:"), synopsis).unwrap();
+    if let Some(description) = &self.description {
+      writeln!(output).unwrap();
+      writeln!(output, "{}", description).unwrap();
+    }
+    if let Some((_, aliases)) = self.names.split_last()
+      && !aliases.is_empty()
+    {
+      writeln!(output).unwrap();
+      writeln!(output, "{} {}", heading("Aliases:"), aliases.join(", ")).unwrap();
+    }
+    let arguments: Vec<(String, String)> = self
+      .args
+      .iter()
+      .map(|arg| (arg.synopsis(), arg.help_description()))
+      .collect();
+    let mut options: Vec<(String, String)> = self
+      .flags
+      .iter()
+      .map(|flag| {
+        let mut display = match flag.short {
+          Some(short) => format!("-{}, --{}", short, flag.long),
+          None => format!("--{}", flag.long),
+        };
+        if let Some(placeholder) = flag.value_placeholder() {
+          display.push_str(&format!(" {}", placeholder));
+        }
+        (display, flag.help_description())
+      })
+      .collect();
+    let help_flags = self.help_flags();
+    if !help_flags.is_empty() {
+      options.push((help_flags.join(", "), "Print help".to_string()));
+    }
+    let width = arguments
+      .iter()
+      .chain(&options)
+      .map(|(display, _)| display.len())
+      .max()
+      .unwrap_or_default();
+    for (title, rows) in [("Arguments:", &arguments), ("Options:", &options)] {
+      if rows.is_empty() {
+        continue;
+      }
+      writeln!(output).unwrap();
+      writeln!(output, "{}", heading(title)).unwrap();
+      for (display, description) in rows {
+        if description.is_empty() {
+          writeln!(output, "  {}", display).unwrap();
+        } else {
+          writeln!(
+            output,
+            "  {:width$}  {}",
+            display,
+            description,
+            width = width
+          )
+          .unwrap();
+        }
+      }
+    }
+    if !self.depends.is_empty() {
+      writeln!(output).unwrap();
+      writeln!(
+        output,
+        "{} {}",
+        heading("Depends on:"),
+        self
+          .depends
+          .iter()
+          .map(|dependency| dependency.node.as_str())
+          .collect::<Vec<_>>()
+          .join(", ")
+      )
+      .unwrap();
+    }
+  }
+  /// Completion candidates for `current`, typed after the command name and `params`
+  fn complete(&self, params: &[String], current: &str) -> Vec<String> {
+    let matching = |candidates: Vec<String>| -> Vec<String> {
+      candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(current))
+        .collect()
+    };
+    let choices = |choices: Option<Vec<&str>>| -> Vec<String> {
+      choices
+        .unwrap_or_default()
+        .into_iter()
+        .map(String::from)
+        .collect()
+    };
+    // Value of the flag typed just before
+    if let Some(flag) = params.last().and_then(|param| self.value_flag(param)) {
+      return matching(choices(flag.choices()));
+    }
+    // Value of a --flag=value
+    if let Some((name, _)) = current
+      .strip_prefix("--")
+      .and_then(|flag| flag.split_once('='))
+    {
+      let flag = self.flags.iter().find(|flag| flag.long == name);
+      let values = flag.map(|flag| choices(flag.choices())).unwrap_or_default();
+      return matching(
+        values
+          .into_iter()
+          .map(|value| format!("--{}={}", name, value))
+          .collect(),
+      );
+    }
+    if current.starts_with('-') {
+      return matching(
+        self
+          .flags
+          .iter()
+          .map(|flag| format!("--{}", flag.long))
+          .collect(),
+      );
+    }
+    // Value of the argument at the current position
+    let mut position = 0;
+    let mut params = params.iter();
+    while let Some(param) = params.next() {
+      if param == "--" {
+        position += params.len();
+        break;
+      } else if param.len() > 1 && param.starts_with('-') {
+        if self.value_flag(param).is_some() {
+          // Skip the flag's value
+          params.next();
+        }
+      } else {
+        position += 1;
+      }
+    }
+    let varargs_position = self.args.iter().position(|arg| arg.is_varargs);
+    let position = varargs_position
+      .filter(|&pos| position >= pos)
+      .unwrap_or(position);
+    match self.args.get(position) {
+      Some(arg) => matching(choices(arg.choices())),
+      None => Vec::new(),
+    }
+  }
+  /// Value flag that takes the next word as its value when given as `param`
+  fn value_flag(&self, param: &str) -> Option<&Flag> {
+    let flag = if let Some(long) = param.strip_prefix("--") {
+      self.flags.iter().find(|flag| flag.long == long)
+    } else {
+      // The last flag of a short bundle takes the value
+      let short = param.strip_prefix('-')?.chars().last()?;
+      self.flags.iter().find(|flag| flag.short == Some(short))
+    };
+    flag.filter(|flag| flag.takes_value)
+  }
+}
+
+impl Argument {
+  /// Allowed values, if the type hint is a list of choices like `<dev|prod>`
+  pub fn choices(&self) -> Option<Vec<&str>> {
+    choices(self.type_hint.as_deref())
+  }
+  /// Built-in type of the value, if the type hint names one
+  pub fn value_type(&self) -> Option<ValueType> {
+    self.type_hint.as_deref().and_then(ValueType::from_hint)
+  }
+  /// Why the default isn't a value the argument accepts, if it has one that isn't
+  pub fn default_error(&self) -> Option<String> {
+    default_error(
+      &self.name,
+      self.choices(),
+      self.value_type(),
+      self.default.as_deref()?,
+    )
+  }
+  /// Description shown in help, including the choices and default value if there are any
+  ///
+  /// A default that isn't accepted is left out, since it isn't what the script gets.
+  pub fn help_description(&self) -> String {
+    help_description(
+      self.description.as_deref(),
+      self.choices(),
+      self
+        .default
+        .as_deref()
+        .filter(|_| self.default_error().is_none()),
+    )
+  }
+  /// How the argument appears in a usage line: `<name>`, `[name]` if optional, or `[name...]` for varargs
+  pub fn synopsis(&self) -> String {
+    if self.is_varargs {
+      format!("[{}...]", self.name)
+    } else if self.optional {
+      format!("[{}]", self.name)
+    } else {
+      format!("<{}>", self.name)
+    }
+  }
+}
+
+impl Flag {
+  /// Allowed values, if the type hint is a list of choices like `<dev|prod>`
+  pub fn choices(&self) -> Option<Vec<&str>> {
+    choices(self.type_hint.as_deref())
+  }
+  /// Built-in type of the value, if the type hint names one
+  pub fn value_type(&self) -> Option<ValueType> {
+    self.type_hint.as_deref().and_then(ValueType::from_hint)
+  }
+  /// Why the default isn't a value the flag accepts, if it has one that isn't
+  pub fn default_error(&self) -> Option<String> {
+    default_error(
+      &format!("--{}", self.long),
+      self.choices(),
+      self.value_type(),
+      self.default.as_deref()?,
+    )
+  }
+  /// Description shown in help, including the choices and default value if there are any
+  ///
+  /// A default that isn't accepted is left out, since it isn't what the script gets.
+  pub fn help_description(&self) -> String {
+    help_description(
+      self.description.as_deref(),
+      self.choices(),
+      self
+        .default
+        .as_deref()
+        .filter(|_| self.default_error().is_none()),
+    )
+  }
+  /// Placeholder for a value flag's value in usage lines, like `<file>`, or `None` for boolean flags
+  fn value_placeholder(&self) -> Option<String> {
+    self
+      .takes_value
+      .then(|| format!("<{}>", self.type_hint.as_deref().unwrap_or("value")))
+  }
+}
+
+/// Error for an attribute that isn't directly above a command
+fn misplaced_attribute(attribute: &str, span: Span) -> Diagnostic {
+  Diagnostic::error(
+    span,
+    format!("Attribute @{} must be directly above a command", attribute),
+  )
+}
+
+fn choices(type_hint: Option<&str>) -> Option<Vec<&str>> {
+  type_hint
+    .filter(|hint| hint.contains('|') && ValueType::from_hint(hint).is_none())
+    .map(|hint| hint.split('|').collect())
+}
+
+fn default_error(
+  name: &str,
+  choices: Option<Vec<&str>>,
+  value_type: Option<ValueType>,
+  default: &str,
+) -> Option<String> {
+  validate_choice(name, choices, default)
+    .and_then(|()| match value_type {
+      // Whether a path exists depends on where and when the script runs
+      Some(ValueType::File | ValueType::Dir) | None => Ok(()),
+      Some(value_type) => validate_type(name, &value_type, default, None),
+    })
+    .err()
+    .map(|error| {
+      error
+        .to_string()
+        .replacen("Invalid value", "Invalid default", 1)
+    })
+}
+
+fn help_description(description: Option<&str>, choices: Option<Vec<&str>>, default: Option<&str>) -> String {
+  let mut details = Vec::new();
+  if let Some(choices) = choices {
+    details.push(format!("choices: {}", choices.join(", ")));
+  }
+  if let Some(default) = default {
+    details.push(format!("default: {}", default));
+  }
+  match description {
+    _ if details.is_empty() => description.unwrap_or_default().to_string(),
+    Some(description) => format!("{} ({})", description, details.join("; ")),
+    None => format!("({})", details.join("; ")),
   }
 }
 
 impl Runfile {
-  /// Generate help output to stdout
-  pub fn generate_help_output(&self, colors: bool) {
-    self.generate_help_output_to_buffer(colors, &mut std::io::stdout());
+  /// The command with the given name or alias
+  pub fn command(&self, name: &str) -> Option<&Command> {
+    self.commands.iter().find(|command| {
+      command
+        .names
+        .iter()
+        .any(|command_name| command_name == name)
+    })
+  }
+  /// Completion candidates for the last of `words`, the words typed after `run`
+  ///
+  /// The first word completes to command names. After it come the command's flags, the choices of a
+  /// value flag, and the choices of the argument at the current position.
+  pub fn complete(&self, words: &[String]) -> Vec<String> {
+    let (current, previous) = words
+      .split_last()
+      .map_or(("", &[][..]), |(current, previous)| {
+        (current.as_str(), previous)
+      });
+    let Some((command_name, params)) = previous.split_first() else {
+      return self
+        .commands
+        .iter()
+        .filter(|command| !command.is_private())
+        .flat_map(|command| &command.names)
+        .filter(|name| name.starts_with(current))
+        .cloned()
+        .collect();
+    };
+    match self.command(command_name) {
+      Some(command) => command.complete(params, current),
+      None => Vec::new(),
+    }
   }
-  /// Generate help output for this runfile
+  /// Generate help output to stdout, including private commands if `all` is set
+  pub fn generate_help_output(&self, colors: bool, all: bool) {
+    self.generate_help_output_to_buffer(colors, all, &mut std::io::stdout());
+  }
+  /// Generate help output for this runfile, leaving out private commands
   pub fn generate_help(&self, colors: bool) -> String {
     let mut output = Vec::new();
-    self.generate_help_output_to_buffer(colors, &mut output);
+    self.generate_help_output_to_buffer(colors, false, &mut output);
     String::from_utf8(output).unwrap_or_default()
   }
   /// Generate help output to a buffer
@@ -228,6 +795,7 @@ impl Runfile {
   /// - Commands indented 2 spaces
   /// - Arguments/flags indented 4 spaces
   /// - Ungrouped commands appear first (if any), then grouped commands
+  /// - Private commands are left out unless `all` is set
   ///
   /// ## Alias Display
   /// - Show all names: `alias1, alias2, mainname` in definition order
@@ -243,9 +811,14 @@ impl Runfile {
   /// - Blank line after each group's commands
   /// - Blank line after ungrouped commands section
   /// - Empty runfile: just a newline
-  fn generate_help_output_to_buffer(&self, colors: bool, output: &mut dyn Write) {
+  fn generate_help_output_to_buffer(&self, colors: bool, all: bool, output: &mut dyn Write) {
+    let commands: Vec<&Command> = self
+      .commands
+      .iter()
+      .filter(|command| all || !command.is_private())
+      .collect();
     // Handle empty runfiles
-    if self.commands.is_empty() {
+    if commands.is_empty() {
       writeln!(output).unwrap();
       return;
     }
@@ -263,7 +836,7 @@ impl Runfile {
     };
     // Group commands by their groups
     let mut grouped_commands = std::collections::HashMap::new();
-    for command in &self.commands {
+    for &command in &commands {
       let group_name = command.group.as_deref().unwrap_or("General");
       grouped_commands
         .entry(group_name)
@@ -273,7 +846,7 @@ impl Runfile {
     // Calculate global max widths across all commands
     let mut global_max_command_len = 0;
     let mut global_max_param_len = 0;
-    for command in &self.commands {
+    for command in &commands {
       let command_display = if !command.names.is_empty() {
         command.names.join(", ")
       } else {
@@ -321,13 +894,18 @@ impl Runfile {
         } else {
           "".to_string()
         };
-        let description = command.description.as_deref().unwrap_or("");
+        // Multi-line descriptions are shown on one line
+        let description = command
+          .description
+          .as_deref()
+          .unwrap_or("")
+          .replace('\n', " ");
         if description.is_empty() {
           // For commands without descriptions, don't add trailing spaces
           writeln!(output, "{}", command_display).unwrap();
         } else {
           let command_padding = " ".repeat(command_align_point.saturating_sub(command_display.len()));
-          let formatted_description = format_description(description);
+          let formatted_description = format_description(&description);
           writeln!(
             output,
             "{}{}{}",
@@ -342,7 +920,7 @@ impl Runfile {
             let optional = if arg.optional { "?" } else { "" };
             format!("{}{}", arg.name, optional)
           };
-          let description = arg.description.as_deref().unwrap_or("");
+          let description = &arg.help_description();
           let formatted_description = format_description(description);
           if description.is_empty() {
             // For items without descriptions, don't add trailing spaces
@@ -364,7 +942,7 @@ impl Runfile {
             String::new()
           };
           let flag_display = format!("{}--{}", short_part, flag.long);
-          let description = flag.description.as_deref().unwrap_or("");
+          let description = &flag.help_description();
           let formatted_description = format_description(description);
           if description.is_empty() {
             // For items without descriptions, don't add trailing spaces
@@ -398,13 +976,18 @@ impl Runfile {
           } else {
             "".to_string()
           };
-          let description = command.description.as_deref().unwrap_or("");
+          // Multi-line descriptions are shown on one line
+          let description = command
+            .description
+            .as_deref()
+            .unwrap_or("")
+            .replace('\n', " ");
           if description.is_empty() {
             // For commands without descriptions, don't add trailing spaces
             writeln!(output, "  {}", command_display).unwrap();
           } else {
             let command_padding = " ".repeat(command_align_point.saturating_sub(command_display.len()));
-            let formatted_description = format_description(description);
+            let formatted_description = format_description(&description);
             writeln!(
               output,
               "  {}{}{}",
@@ -419,7 +1002,7 @@ impl Runfile {
               let optional = if arg.optional { "?" } else { "" };
               format!("{}{}", arg.name, optional)
             };
-            let description = arg.description.as_deref().unwrap_or("");
+            let description = &arg.help_description();
             let formatted_description = format_description(description);
             if description.is_empty() {
               // For items without descriptions, don't add trailing spaces
@@ -441,7 +1024,7 @@ impl Runfile {
               String::new()
             };
             let flag_display = format!("{}--{}", short_part, flag.long);
-            let description = flag.description.as_deref().unwrap_or("");
+            let description = &flag.help_description();
             let formatted_description = format_description(description);
             if description.is_empty() {
               // For items without descriptions, don't add trailing spaces
@@ -472,7 +1055,7 @@ mod tests {
     let tokenizer = TokenizePhase::new();
     let parser = ParsePhase::new();
 
-    let content = "command:\n  arg?\n  --flag\n  echo \"Hello\"";
+    let content = "command\n  arg?\n  --flag:\n  echo \"Hello\"";
     let tokens = tokenizer.tokenize(content).unwrap();
     let runfile = parser.parse(tokens).unwrap();
 
@@ -481,11 +1064,11 @@ mod tests {
     assert_eq!(cmd.names, vec!["command"]);
     assert_eq!(cmd.args.len(), 1);
     assert_eq!(cmd.args[0].name, "arg");
-    assert_eq!(cmd.args[0].optional, true);
-    assert_eq!(cmd.args[0].is_varargs, false);
+    assert!(cmd.args[0].optional);
+    assert!(!cmd.args[0].is_varargs);
     assert_eq!(cmd.flags.len(), 1);
     assert_eq!(cmd.flags[0].long, "flag");
-    assert_eq!(cmd.flags[0].takes_value, false);
+    assert!(!cmd.flags[0].takes_value);
     assert_eq!(cmd.script.trim(), "echo \"Hello\"");
   }
 
@@ -494,7 +1077,7 @@ mod tests {
     let tokenizer = TokenizePhase::new();
     let parser = ParsePhase::new();
 
-    let content = "build:\n  -r, --release\n  --debug\n  echo \"Building\"";
+    let content = "build\n  -r, --release\n  --debug:\n  echo \"Building\"";
     let tokens = tokenizer.tokenize(content).unwrap();
     let runfile = parser.parse(tokens).unwrap();
 
@@ -504,9 +1087,9 @@ mod tests {
     assert_eq!(cmd.flags.len(), 2);
     assert_eq!(cmd.flags[0].short, Some('r'));
     assert_eq!(cmd.flags[0].long, "release");
-    assert_eq!(cmd.flags[0].takes_value, false);
+    assert!(!cmd.flags[0].takes_value);
     assert_eq!(cmd.flags[1].long, "debug");
-    assert_eq!(cmd.flags[1].takes_value, false);
+    assert!(!cmd.flags[1].takes_value);
   }
 
   #[test]
@@ -523,15 +1106,15 @@ mod tests {
     assert_eq!(cmd.names, vec!["command"]);
     assert_eq!(cmd.args.len(), 1);
     assert_eq!(cmd.args[0].name, "arg");
-    assert_eq!(cmd.args[0].optional, false);
-    assert_eq!(cmd.args[0].is_varargs, false);
+    assert!(!cmd.args[0].optional);
+    assert!(!cmd.args[0].is_varargs);
     assert_eq!(cmd.flags.len(), 2);
     assert_eq!(cmd.flags[0].short, Some('f'));
     assert_eq!(cmd.flags[0].long, "flag");
-    assert_eq!(cmd.flags[0].takes_value, false);
+    assert!(!cmd.flags[0].takes_value);
     assert_eq!(cmd.flags[1].short, Some('o'));
     assert_eq!(cmd.flags[1].long, "other");
-    assert_eq!(cmd.flags[1].takes_value, false);
+    assert!(!cmd.flags[1].takes_value);
   }
 
   #[test]
@@ -578,7 +1161,7 @@ mod tests {
     assert_eq!(cmd.names, vec!["hello"]);
     assert_eq!(
       cmd.description,
-      Some("First comment Second comment".to_string())
+      Some("First comment\nSecond comment".to_string())
     );
   }
 
@@ -596,8 +1179,8 @@ mod tests {
     assert_eq!(cmd.names, vec!["run"]);
     assert_eq!(cmd.args.len(), 1);
     assert_eq!(cmd.args[0].name, "args");
-    assert_eq!(cmd.args[0].optional, true);
-    assert_eq!(cmd.args[0].is_varargs, true);
+    assert!(cmd.args[0].optional);
+    assert!(cmd.args[0].is_varargs);
 
     // Test help output
     let help = runfile.generate_help(false);
@@ -630,8 +1213,8 @@ mod tests {
     assert_eq!(cmd.args.len(), 2);
     assert_eq!(cmd.args[0].name, "target");
     assert_eq!(cmd.args[1].name, "args");
-    assert_eq!(cmd.args[1].optional, true);
-    assert_eq!(cmd.args[1].is_varargs, true);
+    assert!(cmd.args[1].optional);
+    assert!(cmd.args[1].is_varargs);
 
     // Test help output
     let help = runfile.generate_help(false);
@@ -686,7 +1269,7 @@ mod tests {
     let tokenizer = TokenizePhase::new();
     let parser = ParsePhase::new();
 
-    let content = "build:\n  --output=<file>\n  echo \"Building\"";
+    let content = "build\n  --output=<file>:\n  echo \"Building\"";
     let tokens = tokenizer.tokenize(content).unwrap();
     let runfile = parser.parse(tokens).unwrap();
 
@@ -695,10 +1278,114 @@ mod tests {
     assert_eq!(cmd.names, vec!["build"]);
     assert_eq!(cmd.flags.len(), 1);
     assert_eq!(cmd.flags[0].long, "output");
-    assert_eq!(cmd.flags[0].takes_value, true);
+    assert!(cmd.flags[0].takes_value);
     assert_eq!(cmd.flags[0].type_hint, Some("file".to_string()));
   }
 
+  #[test]
+  fn test_parse_command_with_defaults() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "# Greet someone\ngreet\n  name=World # Who to greet\n  --mode=<name>=fast:\n  echo \"Hello\"";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+
+    let cmd = &runfile.commands[0];
+    assert_eq!(cmd.args[0].default, Some("World".to_string()));
+    assert!(cmd.args[0].optional);
+    assert_eq!(cmd.flags[0].default, Some("fast".to_string()));
+    let help = runfile.generate_help(false);
+    assert_eq!(
+      help,
+      "greet     # Greet someone\n  name?     # Who to greet (default: World)\n  --mode    # (default: fast)\n"
+    );
+  }
+
+  #[test]
+  fn test_parse_command_with_choices() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "deploy target<web|worker>\n  -e, --env=<dev|prod>=dev: # Environment\n  echo \"Deploying\"";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+
+    let cmd = &runfile.commands[0];
+    assert_eq!(cmd.args[0].name, "target");
+    assert_eq!(cmd.args[0].choices(), Some(vec!["web", "worker"]));
+    assert_eq!(cmd.flags[0].choices(), Some(vec!["dev", "prod"]));
+    let help = runfile.generate_help(false);
+    assert_eq!(
+      help,
+      "deploy\n  target        # (choices: web, worker)\n  -e, --env     # Environment (choices: dev, prod; default: dev)\n"
+    );
+  }
+
+  #[test]
+  fn test_invalid_defaults_left_out_of_help() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "c n<int>=abc\n  --mode=<fast|slow>=medium:\n  echo $n";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+
+    let cmd = &runfile.commands[0];
+    assert_eq!(
+      cmd.args[0].default_error().as_deref(),
+      Some("Invalid default 'abc' for n: expected an integer")
+    );
+    assert_eq!(
+      cmd.flags[0].default_error().as_deref(),
+      Some("Invalid default 'medium' for --mode: expected one of fast, slow")
+    );
+    let help = runfile.generate_help(false);
+    assert_eq!(help, "c\n  n?\n  --mode    # (choices: fast, slow)\n");
+  }
+
+  #[test]
+  fn test_value_type_from_hint() {
+    assert_eq!(ValueType::from_hint("int"), Some(ValueType::Int));
+    assert_eq!(ValueType::from_hint("port"), Some(ValueType::Port));
+    assert_eq!(
+      ValueType::from_hint("regex:a|b"),
+      Some(ValueType::Regex("a|b".to_string()))
+    );
+    assert_eq!(ValueType::from_hint("triple"), None);
+    assert_eq!(ValueType::from_hint("dev|prod"), None);
+  }
+
+  #[test]
+  fn test_complete() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "b, build -r, --release:\n  cargo build\n\ndeploy target<web|worker> ...regions\n  -e, --env=<dev|prod>:\n  echo \"Deploying\"";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+    let complete = |words: &[&str]| {
+      runfile.complete(
+        &words
+          .iter()
+          .map(|word| word.to_string())
+          .collect::<Vec<_>>(),
+      )
+    };
+
+    assert_eq!(complete(&[]), vec!["b", "build", "deploy"]);
+    assert_eq!(complete(&["b"]), vec!["b", "build"]);
+    assert_eq!(complete(&["build", "--"]), vec!["--release"]);
+    assert_eq!(complete(&["deploy", "w"]), vec!["web", "worker"]);
+    assert_eq!(complete(&["deploy", "-e", ""]), vec!["dev", "prod"]);
+    assert_eq!(complete(&["deploy", "--env=p"]), vec!["--env=prod"]);
+    assert_eq!(
+      complete(&["deploy", "--env", "dev", "web", ""]),
+      Vec::<String>::new()
+    );
+    assert_eq!(complete(&["unknown", ""]), Vec::<String>::new());
+  }
+
   #[test]
   fn test_parse_command_with_shebang() {
     let tokenizer = TokenizePhase::new();
@@ -751,7 +1438,7 @@ mod tests {
     let tokenizer = TokenizePhase::new();
     let parser = ParsePhase::new();
 
-    let content = "build:\n  target # The build target\n  echo \"Building\"";
+    let content = "build\n  target: # The build target\n  echo \"Building\"";
     let tokens = tokenizer.tokenize(content).unwrap();
     let runfile = parser.parse(tokens).unwrap();
 
@@ -771,7 +1458,7 @@ mod tests {
     let tokenizer = TokenizePhase::new();
     let parser = ParsePhase::new();
 
-    let content = "build:\n  --debug # Enable debug mode\n  echo \"Building\"";
+    let content = "build\n  --debug: # Enable debug mode\n  echo \"Building\"";
     let tokens = tokenizer.tokenize(content).unwrap();
     let runfile = parser.parse(tokens).unwrap();
 
@@ -786,6 +1473,67 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_parse_spans() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "# ---\n# Build\n# ---\n\nbuild target\n  -r, --release # Optimized\n  --fast:\n  cargo build";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+    let text = |span: Span| &content[span.start..span.end];
+
+    assert_eq!(text(runfile.groups[0].span), "Build");
+    let cmd = &runfile.commands[0];
+    assert_eq!((cmd.span.line, cmd.span.column), (5, 1));
+    assert_eq!(text(cmd.span), "build target");
+    assert_eq!(text(cmd.args[0].span), "target");
+    assert_eq!((cmd.flags[0].span.line, cmd.flags[0].span.column), (6, 3));
+    assert_eq!(text(cmd.flags[0].span), "-r, --release");
+    assert_eq!(text(cmd.flags[1].span), "--fast");
+  }
+
+  #[test]
+  fn test_parse_attribute_error_span() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "build:\n  cargo build\n\n@bogus\ntest:\n  cargo test";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let error = parser.parse(tokens).unwrap_err();
+    let diagnostics = error.downcast_ref::<Diagnostics>().unwrap();
+    let diagnostic = diagnostics.iter().next().unwrap();
+    assert_eq!(diagnostic.message, "Unknown attribute: @bogus");
+    assert_eq!((diagnostic.span.line, diagnostic.span.column), (4, 1));
+  }
+
+  #[test]
+  fn test_parse_continues_past_errors() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "@bogus\nbuild:\n  cargo build\n\n@private extra\ntest:\n  cargo test\n\n@set fuzzy\n@depends build";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let (runfile, diagnostics) = parser.parse_with_diagnostics(tokens);
+
+    let messages: Vec<&str> = diagnostics
+      .iter()
+      .map(|diagnostic| diagnostic.message.as_str())
+      .collect();
+    assert_eq!(
+      messages,
+      vec![
+        "Unknown attribute: @bogus",
+        "Attribute @private takes no arguments",
+        "Unknown setting: fuzzy",
+        "Attribute @depends must be directly above a command",
+      ]
+    );
+    // The commands are still there, without the attributes that were wrong
+    assert_eq!(runfile.commands.len(), 2);
+    assert!(!runfile.commands[1].private);
+  }
+
   #[test]
   fn test_parse_group_header() {
     let tokenizer = TokenizePhase::new();
@@ -867,4 +1615,193 @@ mod tests {
     assert_eq!(cmd.flags[0].short, Some('r'));
     assert_eq!(cmd.flags[0].long, "release");
   }
+
+  #[test]
+  fn test_parse_passthrough_attribute() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "# Run tests\n@passthrough\ntest ...args:\n  cargo test \"$@\"\n\nbuild:\n  cargo build";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+
+    assert_eq!(runfile.commands.len(), 2);
+    assert_eq!(runfile.commands[0].names, vec!["test"]);
+    assert_eq!(
+      runfile.commands[0].description,
+      Some("Run tests".to_string())
+    );
+    assert!(runfile.commands[0].passthrough);
+    assert!(!runfile.commands[1].passthrough);
+  }
+
+  #[test]
+  fn test_parse_depends_attribute() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "# Format, test and lint\n@depends fmt test\n@depends clippy\nverify:\n\nfmt:\n  cargo fmt";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+
+    assert_eq!(runfile.commands[0].names, vec!["verify"]);
+    let depends: Vec<(&str, usize)> = runfile.commands[0]
+      .depends
+      .iter()
+      .map(|dependency| (dependency.node.as_str(), dependency.span.line))
+      .collect();
+    assert_eq!(depends, vec![("fmt", 2), ("test", 2), ("clippy", 3)]);
+    assert!(runfile.commands[0].script.is_empty());
+    assert!(runfile.commands[1].depends.is_empty());
+  }
+
+  #[test]
+  fn test_private_commands_hidden_from_help() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "# Build it\nbuild:\n  cargo build\n\n# Internal helper\n@private\nhelper:\n  echo helper\n\n_setup:\n  echo setup";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+
+    assert!(!runfile.commands[0].is_private());
+    assert!(runfile.commands[1].is_private());
+    assert!(runfile.commands[2].is_private());
+    assert_eq!(runfile.generate_help(false), "build   # Build it\n");
+    let mut all = Vec::new();
+    runfile.generate_help_output_to_buffer(false, true, &mut all);
+    assert_eq!(
+      String::from_utf8(all).unwrap(),
+      "build   # Build it\nhelper  # Internal helper\n_setup\n"
+    );
+    assert_eq!(runfile.complete(&[String::new()]), vec!["build"]);
+  }
+
+  #[test]
+  fn test_command_help() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "# Build the project\n# for one target\n@depends fmt\nb, build target ...args\n  -r, --release # Optimized\n  --mode=<dev|prod>=dev:\n  cargo build\n\nfmt:\n  cargo fmt";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+    let command = &runfile.commands[0];
+
+    assert_eq!(
+      command.usage(),
+      "Usage: run build [-r|--release] [--mode <dev|prod>] <target> [args...]"
+    );
+    assert_eq!(
+      command.generate_help(false),
+      "Usage: run build [-r|--release] [--mode <dev|prod>] <target> [args...]\n\
+       \n\
+       Build the project\n\
+       for one target\n\
+       \n\
+       Aliases: b\n\
+       \n\
+       Arguments:\n\
+       \x20 <target>\n\
+       \x20 [args...]\n\
+       \n\
+       Options:\n\
+       \x20 -r, --release      Optimized\n\
+       \x20 --mode <dev|prod>  (choices: dev, prod; default: dev)\n\
+       \x20 -h, --help         Print help\n\
+       \n\
+       Depends on: fmt\n"
+    );
+    // The listing shows the description on one line
+    assert!(
+      runfile
+        .generate_help(false)
+        .starts_with("b, build          # Build the project for one target\n")
+    );
+  }
+
+  #[test]
+  fn test_command_wants_help() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "build --verbose:\n  cargo build\n\nserve -h, --host=<name>:\n  ./serve.sh";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    let build = &runfile.commands[0];
+    assert!(build.wants_help(&args(&["--help"])));
+    assert!(build.wants_help(&args(&["--verbose", "-h"])));
+    assert!(!build.wants_help(&args(&["--", "--help"])));
+    // serve's own -h is its host flag, so only --help shows help
+    let serve = &runfile.commands[1];
+    assert!(!serve.wants_help(&args(&["-h", "localhost"])));
+    assert!(serve.wants_help(&args(&["--help"])));
+    assert!(
+      serve
+        .generate_help(false)
+        .ends_with("  --help             Print help\n")
+    );
+  }
+
+  #[test]
+  fn test_parse_settings() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "@set prefix-matching\n\n# Build it\nbuild:\n  cargo build";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let runfile = parser.parse(tokens).unwrap();
+    assert!(runfile.settings.prefix_matching);
+    assert_eq!(
+      runfile.commands[0].description,
+      Some("Build it".to_string())
+    );
+
+    let tokens = tokenizer.tokenize("build:\n  cargo build").unwrap();
+    assert!(!parser.parse(tokens).unwrap().settings.prefix_matching);
+
+    let error = |content: &str| {
+      let tokens = tokenizer.tokenize(content).unwrap();
+      parser.parse(tokens).unwrap_err().to_string()
+    };
+    assert_eq!(error("@set fuzzy"), "Unknown setting: fuzzy");
+    assert_eq!(error("@set"), "Attribute @set takes exactly one setting");
+  }
+
+  #[test]
+  fn test_parse_unknown_attribute() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "@bogus\ntest:\n  echo test";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let result = parser.parse(tokens);
+
+    assert!(result.is_err());
+    assert!(
+      result
+        .unwrap_err()
+        .to_string()
+        .contains("Unknown attribute: @bogus")
+    );
+  }
+
+  #[test]
+  fn test_parse_attribute_without_command() {
+    let tokenizer = TokenizePhase::new();
+    let parser = ParsePhase::new();
+
+    let content = "test:\n  echo test\n\n@passthrough\n";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    let result = parser.parse(tokens);
+
+    assert!(result.is_err());
+    assert!(
+      result
+        .unwrap_err()
+        .to_string()
+        .contains("must be directly above a command")
+    );
+  }
 }
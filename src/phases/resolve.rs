@@ -1,90 +1,366 @@
+use std::collections::HashMap;
+
 use anyhow::{Result, anyhow};
 
-use super::parse::{Command, Runfile};
+use super::{
+  diagnostic::{Diagnostic, Diagnostics, Spanned},
+  parse::{Command, Runfile},
+};
+use crate::suggest;
+
+/// Commands to run for one invocation, each listed once and after all of its dependencies
+///
+/// The command that was asked for is the last step.
+#[derive(Debug, Clone)]
+pub struct ExecutionPlan {
+  pub steps: Vec<PlanStep>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlanStep {
+  pub command: Command,
+  /// Indices of the steps that must finish before this one starts
+  pub dependencies: Vec<usize>,
+}
+
+impl ExecutionPlan {
+  /// The command that was asked for
+  pub fn target(&self) -> &Command {
+    &self
+      .steps
+      .last()
+      .expect("a plan always contains its target")
+      .command
+  }
+}
 
 #[derive(Default)]
-pub struct ResolvePhase;
+pub struct ResolvePhase {
+  /// Find the target command from an unambiguous prefix of one of its names, even if the Runfile
+  /// doesn't turn on prefix matching itself
+  pub prefix_matching: bool,
+}
 
 impl ResolvePhase {
   pub fn new() -> Self {
-    Self
+    Self::default()
+  }
+  pub fn with_prefix_matching(prefix_matching: bool) -> Self {
+    Self { prefix_matching }
   }
   pub fn resolve(&self, runfile: Runfile, target_command: &str) -> Result<Command> {
-    self.validate_runfile(&runfile)?;
+    self.validate_runfile(&runfile).into_result()?;
     // Find the command by name or alias
+    let index = self.find_target(&runfile, target_command)?;
     let command = runfile
       .commands
       .into_iter()
-      .find(|cmd| cmd.names.contains(&target_command.to_string()))
-      .ok_or_else(|| anyhow!("Command '{}' not found", target_command))?;
+      .nth(index)
+      .expect("found command exists");
     // Validate the command structure
-    self.validate_command(&command)?;
+    self.validate_command(&command).into_result()?;
     Ok(command)
   }
-  fn validate_runfile(&self, runfile: &Runfile) -> Result<()> {
+  /// Resolve a command together with the commands it depends on, directly or indirectly
+  pub fn resolve_plan(&self, runfile: Runfile, target_command: &str) -> Result<ExecutionPlan> {
+    self.validate_runfile(&runfile).into_result()?;
+    let target = self.find_target(&runfile, target_command)?;
+    let mut planned = HashMap::new();
+    let mut steps = Vec::new();
+    self.plan_command(&runfile, target, &mut Vec::new(), &mut planned, &mut steps)?;
+    let steps = steps
+      .into_iter()
+      .map(|(index, dependencies)| PlanStep {
+        command: runfile.commands[index].clone(),
+        dependencies,
+      })
+      .collect::<Vec<_>>();
+    for step in &steps {
+      self.validate_command(&step.command).into_result()?;
+    }
+    Ok(ExecutionPlan { steps })
+  }
+  /// Index of the command asked for by `name`
+  ///
+  /// With prefix matching, a name that isn't an exact match finds the one public command with a name
+  /// or alias starting with it.
+  fn find_target(&self, runfile: &Runfile, name: &str) -> Result<usize> {
+    if let Some(index) = find_command(runfile, name) {
+      return Ok(index);
+    }
+    if !(self.prefix_matching || runfile.settings.prefix_matching) {
+      return Err(not_found(runfile, name));
+    }
+    let mut matches: Vec<(usize, &str)> = Vec::new();
+    for (index, command) in runfile.commands.iter().enumerate() {
+      if command.is_private() {
+        continue;
+      }
+      for command_name in &command.names {
+        if command_name.starts_with(name) {
+          matches.push((index, command_name));
+        }
+      }
+    }
+    match matches.as_slice() {
+      [] => Err(not_found(runfile, name)),
+      // Several names of the same command still find just that command
+      [(index, _), rest @ ..] if rest.iter().all(|(other, _)| other == index) => Ok(*index),
+      _ => {
+        let candidates: Vec<&str> = matches
+          .iter()
+          .map(|&(_, command_name)| command_name)
+          .collect();
+        Err(anyhow!(
+          "Command '{}' is ambiguous, it could be: {}",
+          name,
+          candidates.join(", ")
+        ))
+      }
+    }
+  }
+  /// Add a command to the plan after its dependencies, returning its step index
+  ///
+  /// `visiting` holds the chain of commands being planned, to detect cycles.
+  fn plan_command(
+    &self,
+    runfile: &Runfile,
+    index: usize,
+    visiting: &mut Vec<usize>,
+    planned: &mut HashMap<usize, usize>,
+    steps: &mut Vec<(usize, Vec<usize>)>,
+  ) -> Result<usize> {
+    if let Some(&step) = planned.get(&index) {
+      return Ok(step);
+    }
+    let command = &runfile.commands[index];
+    if let Some(start) = visiting.iter().position(|&visited| visited == index) {
+      let cycle: Vec<&str> = visiting[start..]
+        .iter()
+        .chain([&index])
+        .map(|&visited| runfile.commands[visited].name())
+        .collect();
+      return Err(
+        Diagnostic::error(
+          command.span,
+          format!("Dependency cycle: {}", cycle.join(" -> ")),
+        )
+        .into(),
+      );
+    }
+    visiting.push(index);
+    let mut dependencies = Vec::new();
+    for Spanned {
+      node: dependency,
+      span,
+    } in &command.depends
+    {
+      let dependency_index = find_command(runfile, dependency).ok_or_else(|| {
+        // Dependencies are often private, so suggest any command
+        let names = runfile
+          .commands
+          .iter()
+          .flat_map(|command| &command.names)
+          .map(String::as_str);
+        let error = format!(
+          "Command '{}' depends on unknown command '{}'",
+          command.name(),
+          dependency
+        );
+        let error = match suggest::closest(dependency, names) {
+          Some(suggestion) => format!("{}\n\nDid you mean '{}'?", error, suggestion),
+          None => error,
+        };
+        anyhow::Error::from(Diagnostic::error(*span, error))
+      })?;
+      let dependency_command = &runfile.commands[dependency_index];
+      if let Some(arg) = dependency_command.args.iter().find(|arg| !arg.optional) {
+        return Err(
+          Diagnostic::error(
+            *span,
+            format!(
+              "Command '{}' depends on '{}', which requires argument '{}'",
+              command.name(),
+              dependency_command.name(),
+              arg.name
+            ),
+          )
+          .into(),
+        );
+      }
+      let step = self.plan_command(runfile, dependency_index, visiting, planned, steps)?;
+      if !dependencies.contains(&step) {
+        dependencies.push(step);
+      }
+    }
+    visiting.pop();
+    steps.push((index, dependencies));
+    planned.insert(index, steps.len() - 1);
+    Ok(steps.len() - 1)
+  }
+  /// Problems with the Runfile as a whole, like two commands sharing a name
+  pub fn validate_runfile(&self, runfile: &Runfile) -> Diagnostics {
+    let mut diagnostics = Diagnostics::new();
     let mut names = std::collections::HashSet::new();
     for command in &runfile.commands {
       for name in &command.names {
         if !names.insert(name.clone()) {
-          return Err(anyhow!("Duplicate command name or alias: {}", name));
+          diagnostics.push(Diagnostic::error(
+            command.span,
+            format!("Duplicate command name or alias: {}", name),
+          ));
+        }
+      }
+    }
+    diagnostics
+  }
+  /// Problems with every command and its dependencies, as running each of them would find them
+  ///
+  /// Together with [`Self::validate_runfile`], this finds the problems of the whole Runfile up front, where
+  /// running a command only finds those of the commands it runs.
+  pub fn validate_commands(&self, runfile: &Runfile) -> Diagnostics {
+    let mut diagnostics = Diagnostics::new();
+    for (index, command) in runfile.commands.iter().enumerate() {
+      let mut found = self.validate_command(command);
+      let planned = self.plan_command(
+        runfile,
+        index,
+        &mut Vec::new(),
+        &mut HashMap::new(),
+        &mut Vec::new(),
+      );
+      if let Err(error) = planned
+        && let Ok(diagnostic) = error.downcast::<Diagnostic>()
+      {
+        found.push(diagnostic);
+      }
+      // A broken dependency is found again from each command that depends on it, so each problem is
+      // reported once for the line it points at
+      for diagnostic in found {
+        let reported = diagnostics
+          .iter()
+          .any(|other| other.span == diagnostic.span && other.message == diagnostic.message);
+        if !reported {
+          diagnostics.push(diagnostic);
         }
       }
     }
-    Ok(())
+    diagnostics.sort();
+    diagnostics
   }
-  fn validate_command(&self, command: &Command) -> Result<()> {
+  /// Problems with the arguments, flags and script of one command
+  pub fn validate_command(&self, command: &Command) -> Diagnostics {
+    let mut diagnostics = Diagnostics::new();
     // Check for duplicate argument names
     let mut arg_names = std::collections::HashSet::new();
-    let mut varargs_count = 0;
-    let mut varargs_position = None;
-    for (i, arg) in command.args.iter().enumerate() {
+    for arg in &command.args {
       if !arg_names.insert(arg.name.clone()) {
-        return Err(anyhow!("Duplicate argument name: {}", arg.name));
-      }
-      if arg.is_varargs {
-        varargs_count += 1;
-        varargs_position = Some(i);
+        diagnostics.push(Diagnostic::error(
+          arg.span,
+          format!("Duplicate argument name: {}", arg.name),
+        ));
       }
     }
     // Validate varargs rules
-    if varargs_count > 1 {
-      return Err(anyhow!("Only one varargs argument (...args) is allowed"));
-    }
-    if let Some(pos) = varargs_position
-      && pos != command.args.len() - 1
-    {
-      return Err(anyhow!(
-        "Varargs argument (...args) must be the last argument"
-      ));
+    let varargs: Vec<usize> = (0..command.args.len())
+      .filter(|&i| command.args[i].is_varargs)
+      .collect();
+    // Both point at the last varargs argument
+    if let [.., last] = varargs.as_slice() {
+      if varargs.len() > 1 {
+        diagnostics.push(Diagnostic::error(
+          command.args[*last].span,
+          "Only one varargs argument (...args) is allowed",
+        ));
+      } else if *last != command.args.len() - 1 {
+        diagnostics.push(Diagnostic::error(
+          command.args[*last].span,
+          "Varargs argument (...args) must be the last argument",
+        ));
+      }
     }
     // Check for duplicate flag names
     let mut flag_names = std::collections::HashSet::new();
     for flag in &command.flags {
       if !flag_names.insert(flag.long.clone()) {
-        return Err(anyhow!("Duplicate flag name: {}", flag.long));
+        diagnostics.push(Diagnostic::error(
+          flag.span,
+          format!("Duplicate flag name: {}", flag.long),
+        ));
       }
       if let Some(short) = flag.short {
         let short_str = short.to_string();
         if !flag_names.insert(short_str) {
-          return Err(anyhow!("Duplicate short flag: -{}", short));
+          diagnostics.push(Diagnostic::error(
+            flag.span,
+            format!("Duplicate short flag: -{}", short),
+          ));
         }
       }
     }
-    // Validate script is not empty
-    if command.script.trim().is_empty() {
-      return Err(anyhow!(
-        "Command '{}' has no script body",
-        command.names.first().unwrap_or(&"unknown".to_string())
+    // Defaults must be values their argument or flag accepts
+    let args = command
+      .args
+      .iter()
+      .filter_map(|arg| Some(Diagnostic::error(arg.span, arg.default_error()?)));
+    let flags = command
+      .flags
+      .iter()
+      .filter_map(|flag| Some(Diagnostic::error(flag.span, flag.default_error()?)));
+    for diagnostic in args.chain(flags) {
+      diagnostics.push(diagnostic);
+    }
+    // Validate script is not empty, unless the command only runs its dependencies
+    if command.script.trim().is_empty() && command.depends.is_empty() {
+      diagnostics.push(Diagnostic::error(
+        command.span,
+        format!(
+          "Command '{}' has no script body",
+          command.names.first().unwrap_or(&"unknown".to_string())
+        ),
       ));
     }
-    Ok(())
+    diagnostics.sort();
+    diagnostics
   }
 }
 
+/// Error for a command name that doesn't exist, suggesting the closest public name or alias
+fn not_found(runfile: &Runfile, name: &str) -> anyhow::Error {
+  let names = runfile
+    .commands
+    .iter()
+    .filter(|command| !command.is_private())
+    .flat_map(|command| &command.names)
+    .map(String::as_str);
+  match suggest::closest(name, names) {
+    Some(suggestion) => anyhow!(
+      "Command '{}' not found\n\nDid you mean '{}'?\nRun `run --list` to see all commands",
+      name,
+      suggestion
+    ),
+    None => anyhow!(
+      "Command '{}' not found\n\nRun `run --list` to see all commands",
+      name
+    ),
+  }
+}
+
+/// Index of the command with the given name or alias
+fn find_command(runfile: &Runfile, name: &str) -> Option<usize> {
+  runfile
+    .commands
+    .iter()
+    .position(|cmd| cmd.names.iter().any(|command_name| command_name == name))
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::phases::parse::{Argument, Command, Runfile};
+  use crate::phases::{
+    diagnostic::Span,
+    parse::{Argument, Command, Runfile, Settings},
+  };
 
   #[test]
   fn test_resolve_finds_command() {
@@ -99,7 +375,12 @@ mod tests {
         flags: vec![],
         script: "echo test".to_string(),
         shebang: "#!/bin/sh".to_string(),
+        passthrough: false,
+        depends: vec![],
+        private: false,
+        span: Span::default(),
       }],
+      settings: Settings::default(),
     };
 
     let command = resolver.resolve(runfile, "test").unwrap();
@@ -112,6 +393,7 @@ mod tests {
     let runfile = Runfile {
       groups: vec![],
       commands: vec![],
+      settings: Settings::default(),
     };
 
     let result = resolver.resolve(runfile, "nonexistent");
@@ -138,19 +420,30 @@ mod tests {
             name: "arg1".to_string(),
             optional: false,
             is_varargs: false,
+            type_hint: None,
+            default: None,
             description: None,
+            span: Span::default(),
           },
           Argument {
             name: "arg1".to_string(),
             optional: true,
             is_varargs: false,
+            type_hint: None,
+            default: None,
             description: None,
+            span: Span::default(),
           },
         ],
         flags: vec![],
         script: "echo test".to_string(),
         shebang: "#!/bin/sh".to_string(),
+        passthrough: false,
+        depends: vec![],
+        private: false,
+        span: Span::default(),
       }],
+      settings: Settings::default(),
     };
 
     let result = resolver.resolve(runfile, "test");
@@ -177,6 +470,10 @@ mod tests {
           flags: vec![],
           script: "echo first".to_string(),
           shebang: "#!/bin/sh".to_string(),
+          passthrough: false,
+          depends: vec![],
+          private: false,
+          span: Span::default(),
         },
         Command {
           names: vec!["compile".to_string(), "build".to_string()],
@@ -186,8 +483,13 @@ mod tests {
           flags: vec![],
           script: "echo second".to_string(),
           shebang: "#!/bin/sh".to_string(),
+          passthrough: false,
+          depends: vec![],
+          private: false,
+          span: Span::default(),
         },
       ],
+      settings: Settings::default(),
     };
 
     let result = resolver.resolve(runfile, "build");
@@ -213,7 +515,12 @@ mod tests {
         flags: vec![],
         script: "".to_string(),
         shebang: "#!/bin/sh".to_string(),
+        passthrough: false,
+        depends: vec![],
+        private: false,
+        span: Span::default(),
       }],
+      settings: Settings::default(),
     };
 
     let result = resolver.resolve(runfile, "test");
@@ -225,4 +532,301 @@ mod tests {
         .contains("has no script body")
     );
   }
+
+  #[test]
+  fn test_resolve_error_points_at_problem() {
+    let resolver = ResolvePhase::new();
+    let content = "build --fast\n  -f, --fast:\n  cargo build";
+    let tokens = crate::phases::TokenizePhase::new()
+      .tokenize(content)
+      .unwrap();
+    let runfile = crate::phases::ParsePhase::new().parse(tokens).unwrap();
+
+    let error = resolver.resolve(runfile, "build").unwrap_err();
+    let diagnostics = error.downcast_ref::<Diagnostics>().unwrap();
+    let diagnostic = diagnostics.iter().next().unwrap();
+    assert_eq!(diagnostic.message, "Duplicate flag name: fast");
+    assert_eq!(
+      &content[diagnostic.span.start..diagnostic.span.end],
+      "-f, --fast"
+    );
+  }
+
+  #[test]
+  fn test_validate_commands_finds_every_problem() {
+    let resolver = ResolvePhase::new();
+    let content = "build ...files target --fast\n  --fast:\n  cargo build\n\n@depends lint\ntest:\n\n\
+      @depends generate\nlint:\n  cargo clippy\n\nfmt --mode=<fast|slow>=medium\n  n<int>=abc\n  \
+      config<file>=missing.toml:\n  cargo fmt\n";
+    let tokens = crate::phases::TokenizePhase::new()
+      .tokenize(content)
+      .unwrap();
+    let runfile = crate::phases::ParsePhase::new().parse(tokens).unwrap();
+
+    let messages: Vec<String> = resolver
+      .validate_commands(&runfile)
+      .into_iter()
+      .map(|diagnostic| diagnostic.message)
+      .collect();
+    // test only runs lint, whose unknown dependency is reported once, and files can still be created
+    assert_eq!(
+      messages,
+      vec![
+        "Varargs argument (...args) must be the last argument",
+        "Duplicate flag name: fast",
+        "Command 'lint' depends on unknown command 'generate'",
+        "Invalid default 'medium' for --mode: expected one of fast, slow",
+        "Invalid default 'abc' for n: expected an integer",
+      ]
+    );
+  }
+
+  #[test]
+  fn test_validate_commands_reports_each_problem_once() {
+    let resolver = ResolvePhase::new();
+    let content = "c n<int>=abc:\n  echo $n\n\n@depends c\nd:\n\n@depends c\ne:\n";
+    let tokens = crate::phases::TokenizePhase::new()
+      .tokenize(content)
+      .unwrap();
+    let runfile = crate::phases::ParsePhase::new().parse(tokens).unwrap();
+
+    let diagnostics = resolver.validate_commands(&runfile);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+      diagnostics.iter().next().unwrap().message,
+      "Invalid default 'abc' for n: expected an integer"
+    );
+    // Parsing adds nothing more on the way to run --validate
+    assert_eq!(crate::validate_runfile(content).len(), 1);
+  }
+
+  fn command_with_depends(name: &str, depends: &[&str]) -> Command {
+    Command {
+      names: vec![name.to_string()],
+      description: None,
+      group: None,
+      args: vec![],
+      flags: vec![],
+      script: format!("echo {}", name),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: depends
+        .iter()
+        .map(|depend| Spanned {
+          node: depend.to_string(),
+          span: Span::default(),
+        })
+        .collect(),
+      private: false,
+      span: Span::default(),
+    }
+  }
+
+  #[test]
+  fn test_resolve_plan_orders_dependencies() {
+    let resolver = ResolvePhase::new();
+    let mut verify = command_with_depends("verify", &["fmt", "test", "clippy"]);
+    verify.script = String::new();
+    let runfile = Runfile {
+      groups: vec![],
+      commands: vec![
+        command_with_depends("fmt", &[]),
+        command_with_depends("check", &["fmt"]),
+        command_with_depends("test", &["check"]),
+        command_with_depends("clippy", &["check", "fmt"]),
+        verify,
+      ],
+      settings: Settings::default(),
+    };
+
+    let plan = resolver.resolve_plan(runfile, "verify").unwrap();
+    let names: Vec<&str> = plan.steps.iter().map(|step| step.command.name()).collect();
+    assert_eq!(names, vec!["fmt", "check", "test", "clippy", "verify"]);
+    let dependencies: Vec<&[usize]> = plan
+      .steps
+      .iter()
+      .map(|step| &step.dependencies[..])
+      .collect();
+    assert_eq!(dependencies, vec![&[][..], &[0], &[1], &[1, 0], &[0, 2, 3]]);
+    assert_eq!(plan.target().name(), "verify");
+  }
+
+  #[test]
+  fn test_resolve_plan_dependency_cycle() {
+    let resolver = ResolvePhase::new();
+    let runfile = Runfile {
+      groups: vec![],
+      commands: vec![
+        command_with_depends("build", &["test"]),
+        command_with_depends("test", &["lint"]),
+        command_with_depends("lint", &["build"]),
+      ],
+      settings: Settings::default(),
+    };
+
+    let result = resolver.resolve_plan(runfile, "test");
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "Dependency cycle: test -> lint -> build -> test"
+    );
+  }
+
+  #[test]
+  fn test_resolve_plan_unknown_dependency() {
+    let resolver = ResolvePhase::new();
+    let runfile = Runfile {
+      groups: vec![],
+      commands: vec![command_with_depends("build", &["generate"])],
+      settings: Settings::default(),
+    };
+
+    let result = resolver.resolve_plan(runfile, "build");
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "Command 'build' depends on unknown command 'generate'"
+    );
+  }
+
+  #[test]
+  fn test_dependency_errors_point_at_depends_line() {
+    let resolver = ResolvePhase::new();
+    let content = "deploy target:\n  echo $target\n\n@depends deploy\nrelease:\n\n# Build\n@depends generate\nbuild:\n";
+    let tokens = crate::phases::TokenizePhase::new()
+      .tokenize(content)
+      .unwrap();
+    let runfile = crate::phases::ParsePhase::new().parse(tokens).unwrap();
+
+    let lines: Vec<(usize, String)> = resolver
+      .validate_commands(&runfile)
+      .into_iter()
+      .map(|diagnostic| (diagnostic.span.line, diagnostic.message))
+      .collect();
+    assert_eq!(
+      lines,
+      vec![
+        (
+          4,
+          "Command 'release' depends on 'deploy', which requires argument 'target'".to_string()
+        ),
+        (
+          8,
+          "Command 'build' depends on unknown command 'generate'".to_string()
+        ),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_resolve_suggests_similar_command() {
+    let resolver = ResolvePhase::new();
+    let mut generate = command_with_depends("generate", &[]);
+    generate.private = true;
+    let runfile = Runfile {
+      groups: vec![],
+      commands: vec![
+        command_with_depends("build", &[]),
+        generate,
+        command_with_depends("lint", &["genrate"]),
+      ],
+      settings: Settings::default(),
+    };
+
+    let result = resolver.resolve(runfile.clone(), "biuld");
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "Command 'biuld' not found\n\nDid you mean 'build'?\nRun `run --list` to see all commands"
+    );
+    // Private commands aren't suggested to run, only as dependencies
+    let result = resolver.resolve(runfile.clone(), "generat");
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "Command 'generat' not found\n\nRun `run --list` to see all commands"
+    );
+    let result = resolver.resolve_plan(runfile, "lint");
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "Command 'lint' depends on unknown command 'genrate'\n\nDid you mean 'generate'?"
+    );
+  }
+
+  #[test]
+  fn test_resolve_prefix_matching() {
+    let mut install = command_with_depends("install", &[]);
+    install.names.insert(0, "i".to_string());
+    let mut runfile = Runfile {
+      groups: vec![],
+      commands: vec![
+        install,
+        command_with_depends("install-extension", &[]),
+        command_with_depends("lint", &[]),
+        command_with_depends("_internal", &[]),
+      ],
+      settings: Settings::default(),
+    };
+
+    // Exact names only, unless prefix matching is on
+    assert!(ResolvePhase::new().resolve(runfile.clone(), "li").is_err());
+    let resolver = ResolvePhase::with_prefix_matching(true);
+    assert_eq!(
+      resolver.resolve(runfile.clone(), "li").unwrap().name(),
+      "lint"
+    );
+    // An exact match wins over longer names starting with it
+    assert_eq!(
+      resolver.resolve(runfile.clone(), "install").unwrap().name(),
+      "install"
+    );
+    assert_eq!(
+      resolver
+        .resolve(runfile.clone(), "install-")
+        .unwrap()
+        .name(),
+      "install-extension"
+    );
+    assert_eq!(
+      resolver
+        .resolve(runfile.clone(), "ins")
+        .unwrap_err()
+        .to_string(),
+      "Command 'ins' is ambiguous, it could be: install, install-extension"
+    );
+    // Private commands are only found by their exact name
+    assert!(resolver.resolve(runfile.clone(), "_int").is_err());
+    // The Runfile can turn prefix matching on too
+    runfile.settings.prefix_matching = true;
+    assert_eq!(
+      ResolvePhase::new()
+        .resolve_plan(runfile, "lin")
+        .unwrap()
+        .target()
+        .name(),
+      "lint"
+    );
+  }
+
+  #[test]
+  fn test_resolve_plan_dependency_with_required_argument() {
+    let resolver = ResolvePhase::new();
+    let mut deploy = command_with_depends("deploy", &[]);
+    deploy.args.push(Argument {
+      name: "target".to_string(),
+      optional: false,
+      is_varargs: false,
+      type_hint: None,
+      default: None,
+      description: None,
+      span: Span::default(),
+    });
+    let runfile = Runfile {
+      groups: vec![],
+      commands: vec![deploy, command_with_depends("release", &["deploy"])],
+      settings: Settings::default(),
+    };
+
+    let result = resolver.resolve_plan(runfile, "release");
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "Command 'release' depends on 'deploy', which requires argument 'target'"
+    );
+  }
 }
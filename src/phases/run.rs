@@ -1,28 +1,153 @@
 use std::{
   collections::{HashMap, HashSet},
+  fmt,
+  io::{self, IsTerminal, Write},
   path::Path,
-  process::{Command as ProcessCommand, Output, Stdio},
+  process::{Command as ProcessCommand, ExitStatus, Output},
+  sync::{
+    Mutex,
+    atomic::{AtomicBool, Ordering},
+    mpsc,
+  },
+  thread,
+  time::Duration,
 };
 
+use ansi_term::Colour;
 use anyhow::{Result, anyhow};
+use regex::Regex;
 
-use super::parse::Command;
+use super::{
+  parse::{Argument, Command, Flag, ValueType},
+  resolve::ExecutionPlan,
+};
+use crate::{
+  process::{self, Stream},
+  suggest,
+};
+
+/// Default time a script gets to exit after a forwarded signal before it is killed
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Colors of the output prefixes of commands running in parallel, assigned in plan order
+const PREFIX_COLOURS: [Colour; 6] = [
+  Colour::Cyan,
+  Colour::Yellow,
+  Colour::Green,
+  Colour::Purple,
+  Colour::Blue,
+  Colour::Red,
+];
 
 // Type aliases for complex return types
 type CliArgsResult = (Vec<String>, HashSet<String>, HashMap<String, String>);
 
+/// How many commands of a plan may run at once, and whether a failure stops the others
+#[derive(Clone, Copy, Debug)]
+pub struct Parallelism {
+  pub jobs: usize,
+  pub keep_going: bool,
+}
+
+/// Where the prefixed output of commands running in parallel is written
+pub struct ParallelOutput {
+  pub stdout: Mutex<Box<dyn Write + Send>>,
+  pub stderr: Mutex<Box<dyn Write + Send>>,
+  /// Whether each command's prefix gets its own color
+  pub colors: bool,
+}
+
+impl ParallelOutput {
+  /// The runner's own stdout and stderr, with colors when stdout is a terminal
+  pub fn stdio() -> Self {
+    Self {
+      stdout: Mutex::new(Box::new(io::stdout())),
+      stderr: Mutex::new(Box::new(io::stderr())),
+      colors: io::stdout().is_terminal(),
+    }
+  }
+  fn prefix(&self, index: usize, prefix: &str) -> String {
+    if self.colors {
+      PREFIX_COLOURS[index % PREFIX_COLOURS.len()]
+        .paint(prefix)
+        .to_string()
+    } else {
+      prefix.to_string()
+    }
+  }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum OutputMode {
   Inherit,
   Capture,
 }
 
-#[derive(Default)]
-pub struct RunPhase;
+/// Error returned when a script finishes unsuccessfully
+///
+/// Carries the script's exit status so callers can propagate it exactly, along with the captured
+/// output when the script was run in capture mode.
+#[derive(Debug)]
+pub struct ScriptError {
+  pub status: ExitStatus,
+  pub output: Option<Output>,
+}
+
+impl ScriptError {
+  /// Exit code the script returned, if it exited normally
+  pub fn code(&self) -> Option<i32> {
+    self.status.code()
+  }
+  /// Signal that terminated the script, if any
+  #[cfg(unix)]
+  pub fn signal(&self) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    self.status.signal()
+  }
+  #[cfg(not(unix))]
+  pub fn signal(&self) -> Option<i32> {
+    None
+  }
+  /// Exit code the runner should exit with: the script's own code, or 128 + signal when signaled
+  pub fn exit_code(&self) -> i32 {
+    match (self.code(), self.signal()) {
+      (Some(code), _) => code,
+      (None, Some(signal)) => 128 + signal,
+      (None, None) => 1,
+    }
+  }
+}
+
+impl fmt::Display for ScriptError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match (self.code(), self.signal()) {
+      (None, Some(signal)) => write!(f, "Command terminated by signal: {}", signal),
+      _ => write!(f, "Command failed with exit code: {}", self.exit_code()),
+    }
+  }
+}
+
+impl std::error::Error for ScriptError {}
+
+pub struct RunPhase {
+  /// Time a script gets to exit after a forwarded signal before its process group is killed
+  pub grace_period: Duration,
+}
+
+impl Default for RunPhase {
+  fn default() -> Self {
+    Self {
+      grace_period: DEFAULT_GRACE_PERIOD,
+    }
+  }
+}
 
 impl RunPhase {
   pub fn new() -> Self {
-    Self
+    Self::default()
+  }
+  pub fn with_grace_period(grace_period: Duration) -> Self {
+    Self { grace_period }
   }
   pub fn run(&self, command: Command, cli_args: Vec<String>, mode: OutputMode) -> Result<Option<Output>> {
     self.run_in_directory(command, cli_args, mode, None)
@@ -34,19 +159,193 @@ impl RunPhase {
     mode: OutputMode,
     current_dir: Option<&Path>,
   ) -> Result<Option<Output>> {
+    let (positional_args, env_vars) = self.prepare(&command, cli_args, current_dir)?;
+    self.execute_script(&command, &positional_args, env_vars, mode, current_dir)
+  }
+  /// Run every step of a plan, starting each as soon as its dependencies have succeeded
+  ///
+  /// At most `parallelism.jobs` commands run at once. Each line they write is prefixed with the
+  /// command's name. The first failure stops the other running commands and nothing new is started,
+  /// unless `parallelism.keep_going` is set, in which case only the commands depending on the failed
+  /// one are skipped. The command line arguments go to the plan's target; the first failure is
+  /// returned once everything has stopped.
+  pub fn run_parallel(
+    &self,
+    plan: ExecutionPlan,
+    cli_args: Vec<String>,
+    parallelism: Parallelism,
+    current_dir: Option<&Path>,
+    output: &ParallelOutput,
+  ) -> Result<()> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+      Pending,
+      Running,
+      Succeeded,
+      Failed,
+    }
+    let steps = plan.steps;
+    let target = steps.len() - 1;
+    let width = steps
+      .iter()
+      .map(|step| step.command.name().len())
+      .max()
+      .unwrap_or_default();
+    let mut states = vec![State::Pending; steps.len()];
+    let mut cli_args = Some(cli_args);
+    let cancel = AtomicBool::new(false);
+    let (finished, results) = mpsc::channel();
+    thread::scope(|scope| {
+      let mut running = 0;
+      let mut first_error = None;
+      loop {
+        // A step whose dependency failed never runs, so it counts as failed too
+        for index in 0..steps.len() {
+          if states[index] == State::Pending
+            && steps[index]
+              .dependencies
+              .iter()
+              .any(|&dependency| states[dependency] == State::Failed)
+          {
+            states[index] = State::Failed;
+          }
+        }
+        if !cancel.load(Ordering::Relaxed) {
+          for (index, step) in steps.iter().enumerate() {
+            if running >= parallelism.jobs.max(1) {
+              break;
+            }
+            let ready = step
+              .dependencies
+              .iter()
+              .all(|&dependency| states[dependency] == State::Succeeded);
+            if states[index] != State::Pending || !ready {
+              continue;
+            }
+            states[index] = State::Running;
+            running += 1;
+            let args = if index == target {
+              cli_args.take().unwrap_or_default()
+            } else {
+              Vec::new()
+            };
+            let prefix = output.prefix(index, &format!("{:width$} | ", step.command.name()));
+            let (cancel, finished) = (&cancel, finished.clone());
+            scope.spawn(move || {
+              let result = self.run_prefixed(&step.command, args, current_dir, &prefix, cancel, output);
+              // The scheduler outlives every step, so the receiver is still there
+              finished.send((index, result)).ok();
+            });
+          }
+        }
+        if running == 0 {
+          break;
+        }
+        let (index, result) = results.recv().expect("a running step always reports back");
+        running -= 1;
+        match result {
+          Ok(()) => states[index] = State::Succeeded,
+          Err(err) => {
+            states[index] = State::Failed;
+            first_error.get_or_insert(err);
+            if !parallelism.keep_going {
+              cancel.store(true, Ordering::Relaxed);
+            }
+          }
+        }
+      }
+      first_error.map_or(Ok(()), Err)
+    })
+  }
+  /// Run a command, writing each line of its output with a prefix
+  fn run_prefixed(
+    &self,
+    command: &Command,
+    cli_args: Vec<String>,
+    current_dir: Option<&Path>,
+    prefix: &str,
+    cancel: &AtomicBool,
+    output: &ParallelOutput,
+  ) -> Result<()> {
+    let (positional_args, env_vars) = self.prepare(command, cli_args, current_dir)?;
+    let mut cmd = self.script_command(command, &positional_args, env_vars, current_dir);
+    let status = process::lines(&mut cmd, self.grace_period, cancel, &|stream, line| {
+      let mut prefixed = Vec::with_capacity(prefix.len() + line.len() + 1);
+      prefixed.extend_from_slice(prefix.as_bytes());
+      prefixed.extend_from_slice(line);
+      prefixed.push(b'\n');
+      let writer = match stream {
+        Stream::Stdout => &output.stdout,
+        Stream::Stderr => &output.stderr,
+      };
+      // Output is best effort; a closed pipe shouldn't stop the command
+      let mut writer = writer.lock().unwrap_or_else(|err| err.into_inner());
+      writer
+        .write_all(&prefixed)
+        .and_then(|()| writer.flush())
+        .ok();
+    })?;
+    if !status.success() {
+      return Err(
+        ScriptError {
+          status,
+          output: None,
+        }
+        .into(),
+      );
+    }
+    Ok(())
+  }
+  /// Parse and check the command line arguments, returning the positional parameters and environment
+  /// variables for the script
+  fn prepare(
+    &self,
+    command: &Command,
+    cli_args: Vec<String>,
+    current_dir: Option<&Path>,
+  ) -> Result<(Vec<String>, HashMap<String, String>)> {
     // Parse CLI arguments and flags
-    let (provided_args, provided_flags, provided_flag_values) = self.parse_cli_args(&command, cli_args)?;
+    let (mut provided_args, provided_flags, mut provided_flag_values) = self.parse_cli_args(command, cli_args)?;
     // Validate required arguments are provided
-    self.validate_required_args(&command, &provided_args)?;
+    self.validate_required_args(command, &provided_args)?;
+    self.validate_types(command, &provided_args, &provided_flag_values, current_dir)?;
+    // Fill in defaults for missing trailing arguments, so they also reach the positional parameters. These stop
+    // at the first argument without one, and later defaults only reach the environment variables
+    for arg in command.args.iter().skip(provided_args.len()) {
+      match &arg.default {
+        Some(default) if !arg.is_varargs => provided_args.push(default.clone()),
+        _ => break,
+      }
+    }
+    // Value flags that were not given take their default
+    for flag in &command.flags {
+      if let Some(default) = &flag.default {
+        provided_flag_values
+          .entry(flag.long.clone())
+          .or_insert_with(|| default.clone());
+      }
+    }
     // Set up environment variables
     let mut env_vars = HashMap::new();
     // Set argument values (both UPPER_SNAKE and lower_snake)
     for (i, arg) in command.args.iter().enumerate() {
-      if let Some(value) = provided_args.get(i) {
+      let value = if arg.is_varargs {
+        // Varargs collect every remaining positional argument, also exposed one per variable
+        let varargs = provided_args.get(i..).unwrap_or_default();
+        let key_upper = arg.name.to_uppercase();
+        for (index, value) in varargs.iter().enumerate() {
+          env_vars.insert(format!("{}_{}", key_upper, index), value.clone());
+        }
+        env_vars.insert(format!("{}_COUNT", key_upper), varargs.len().to_string());
+        (!varargs.is_empty()).then(|| shell_join(varargs))
+      } else {
+        provided_args.get(i).or(arg.default.as_ref()).cloned()
+      };
+      if let Some(value) = value {
         // UPPER_SNAKE for values
         env_vars.insert(arg.name.to_uppercase(), value.clone());
         // lower_snake for convenience (same value)
-        env_vars.insert(arg.name.clone(), value.clone());
+        env_vars.insert(arg.name.clone(), value);
       }
     }
     // Set flag values (both UPPER_SNAKE and lower_snake), with '-' replaced by '_' in env var names
@@ -69,90 +368,158 @@ impl RunPhase {
         env_vars.insert(key_lower, flag_string);
       }
     }
-    // Execute the script
-    self.execute_script(&command, env_vars, mode, current_dir)
+    Ok((provided_args, env_vars))
+  }
+  /// Check command line arguments for a command without running it
+  pub fn validate_args(&self, command: &Command, cli_args: Vec<String>, current_dir: Option<&Path>) -> Result<()> {
+    let (provided_args, _, provided_flag_values) = self.parse_cli_args(command, cli_args)?;
+    self.validate_required_args(command, &provided_args)?;
+    self.validate_types(command, &provided_args, &provided_flag_values, current_dir)
   }
   fn parse_cli_args(&self, command: &Command, cli_args: Vec<String>) -> Result<CliArgsResult> {
     let mut provided_args = Vec::new();
     let mut provided_flags = HashSet::new();
     let mut provided_flag_values = HashMap::new();
-    let mut i = 0;
-    // Find varargs argument if it exists
-    let varargs_arg = command.args.iter().find(|arg| arg.is_varargs);
-    while i < cli_args.len() {
-      let arg = &cli_args[i];
-      if arg.starts_with("--") {
-        // Long flag
-        if arg.contains('=') {
-          // Value flag: --flag=value
-          let parts: Vec<&str> = arg.splitn(2, '=').collect();
-          if parts.len() == 2 {
-            let flag_name = parts[0].strip_prefix("--").unwrap().to_string();
-            let flag_value = parts[1].to_string();
-            if let Some(flag) = command
-              .flags
-              .iter()
-              .find(|f| f.long == flag_name && f.takes_value)
-            {
-              provided_flag_values.insert(flag.long.clone(), flag_value);
-            } else {
-              return Err(anyhow!("Unknown value flag: --{}", flag_name));
-            }
+    let varargs_position = command.args.iter().position(|arg| arg.is_varargs);
+    let mut cli_args = cli_args.into_iter();
+    while let Some(arg) = cli_args.next() {
+      if arg == "--" {
+        // Everything after `--` is positional, even if it looks like a flag
+        provided_args.extend(cli_args.by_ref());
+        break;
+      }
+      // Passthrough commands forward unknown flags once their varargs have started
+      let pass_unknown = command.passthrough && varargs_position.is_some_and(|pos| provided_args.len() >= pos);
+      if let Some(long) = arg.strip_prefix("--") {
+        // Long flag: --flag, --flag=value or --flag value
+        let (flag_name, inline_value) = match long.split_once('=') {
+          Some((name, value)) => (name, Some(value.to_string())),
+          None => (long, None),
+        };
+        let Some(flag) = command.flags.iter().find(|f| f.long == flag_name) else {
+          if pass_unknown {
+            provided_args.push(arg);
+            continue;
           }
+          return Err(unknown_flag(command, flag_name));
+        };
+        if flag.takes_value {
+          let value = match inline_value {
+            Some(value) => value,
+            None => cli_args
+              .next()
+              .ok_or_else(|| anyhow!("Flag --{} requires a value", flag.long))?,
+          };
+          provided_flag_values.insert(flag.long.clone(), value);
+        } else if inline_value.is_some() {
+          return Err(anyhow!("Flag --{} does not take a value", flag.long));
         } else {
-          // Boolean flag: --flag
-          let flag_name = arg.strip_prefix("--").unwrap().to_string();
-          if let Some(flag) = command
-            .flags
-            .iter()
-            .find(|f| f.long == flag_name && !f.takes_value)
+          provided_flags.insert(flag.long.clone());
+        }
+      } else if let Some(short) = arg.strip_prefix('-').filter(|short| !short.is_empty()) {
+        // Short flags: -f, bundled booleans like -rv, and a value flag that takes the rest of the
+        // bundle (-ovalue, -o=value, -rvovalue) or, at the end of the bundle, the next argument
+        let mut bundle: Vec<(&Flag, Option<&str>)> = Vec::new();
+        let mut unknown = None;
+        for (index, short_char) in short.char_indices() {
+          if short_char == '='
+            && let Some((previous, _)) = bundle.last()
           {
-            provided_flags.insert(flag.long.clone());
-          } else {
-            return Err(anyhow!("Unknown flag: --{}", flag_name));
+            return Err(anyhow!(
+              "Flag -{} does not take a value",
+              previous.short.unwrap_or_default()
+            ));
+          }
+          match command.flags.iter().find(|f| f.short == Some(short_char)) {
+            Some(flag) if flag.takes_value => {
+              bundle.push((flag, Some(&short[index + short_char.len_utf8()..])));
+              break;
+            }
+            Some(flag) => bundle.push((flag, None)),
+            None => {
+              unknown = Some(short_char);
+              break;
+            }
           }
         }
-      } else if arg.starts_with('-') && arg.len() == 2 {
-        // Short flag
-        let short_char = arg.chars().nth(1).unwrap();
-        if let Some(flag) = command.flags.iter().find(|f| f.short == Some(short_char)) {
-          if flag.takes_value {
-            // Value flag: need to get the value from next argument
-            if i + 1 < cli_args.len() {
-              let flag_value = cli_args[i + 1].clone();
-              provided_flag_values.insert(flag.long.clone(), flag_value);
-              i += 1; // Skip the value argument
-            } else {
-              return Err(anyhow!("Flag -{} requires a value", short_char));
+        if let Some(short_char) = unknown {
+          // Negative numbers are positional unless a digit is declared as a short flag
+          if pass_unknown || arg.parse::<f64>().is_ok() {
+            provided_args.push(arg);
+            continue;
+          }
+          return Err(unknown_short_flag(command, short_char, short));
+        }
+        for (flag, rest) in bundle {
+          match rest {
+            Some("") => {
+              let value = cli_args
+                .next()
+                .ok_or_else(|| anyhow!("Flag -{} requires a value", flag.short.unwrap_or_default()))?;
+              provided_flag_values.insert(flag.long.clone(), value);
+            }
+            Some(rest) => {
+              provided_flag_values.insert(
+                flag.long.clone(),
+                rest.strip_prefix('=').unwrap_or(rest).to_string(),
+              );
+            }
+            None => {
+              provided_flags.insert(flag.long.clone());
             }
-          } else {
-            // Boolean flag
-            provided_flags.insert(flag.long.clone());
           }
-        } else {
-          return Err(anyhow!("Unknown short flag: -{}", short_char));
         }
       } else {
         // Positional argument
-        provided_args.push(arg.clone());
+        provided_args.push(arg);
+      }
+    }
+    self.validate_choices(command, &provided_args, &provided_flag_values)?;
+    Ok((provided_args, provided_flags, provided_flag_values))
+  }
+  fn validate_choices(
+    &self,
+    command: &Command,
+    provided_args: &[String],
+    provided_flag_values: &HashMap<String, String>,
+  ) -> Result<()> {
+    for (index, value) in provided_args.iter().enumerate() {
+      if let Some(arg) = arg_at(command, index) {
+        validate_choice(&arg.name, arg.choices(), value)?;
       }
-      i += 1;
     }
-    // Handle varargs: collect remaining args if varargs is present
-    if let Some(varargs_arg) = varargs_arg {
-      let varargs_position = command
-        .args
-        .iter()
-        .position(|a| a.name == varargs_arg.name)
-        .unwrap();
-      if provided_args.len() > varargs_position {
-        // Collect all remaining args into varargs
-        let varargs: Vec<String> = provided_args.split_off(varargs_position);
-        let varargs_string = varargs.join(" ");
-        provided_args.push(varargs_string);
+    for flag in &command.flags {
+      if let Some(value) = provided_flag_values.get(&flag.long) {
+        validate_choice(&format!("--{}", flag.long), flag.choices(), value)?;
       }
     }
-    Ok((provided_args, provided_flags, provided_flag_values))
+    Ok(())
+  }
+  /// Check provided values against the built-in types of their arguments and flags
+  ///
+  /// Paths are checked relative to the directory the script runs in.
+  fn validate_types(
+    &self,
+    command: &Command,
+    provided_args: &[String],
+    provided_flag_values: &HashMap<String, String>,
+    current_dir: Option<&Path>,
+  ) -> Result<()> {
+    for (index, value) in provided_args.iter().enumerate() {
+      if let Some(arg) = arg_at(command, index)
+        && let Some(value_type) = arg.value_type()
+      {
+        validate_type(&arg.name, &value_type, value, current_dir)?;
+      }
+    }
+    for flag in &command.flags {
+      if let Some(value) = provided_flag_values.get(&flag.long)
+        && let Some(value_type) = flag.value_type()
+      {
+        validate_type(&format!("--{}", flag.long), &value_type, value, current_dir)?;
+      }
+    }
+    Ok(())
   }
   fn validate_required_args(&self, command: &Command, provided_args: &[String]) -> Result<()> {
     for arg in &command.args {
@@ -169,13 +536,14 @@ impl RunPhase {
     }
     Ok(())
   }
-  fn execute_script(
+  /// Build the process that runs a command's script
+  fn script_command(
     &self,
     command: &Command,
+    positional_args: &[String],
     env_vars: HashMap<String, String>,
-    mode: OutputMode,
     current_dir: Option<&Path>,
-  ) -> Result<Option<Output>> {
+  ) -> ProcessCommand {
     // Extract the shell from shebang
     let shell = if command.shebang.starts_with("#!") {
       command.shebang.strip_prefix("#!").unwrap().trim()
@@ -184,7 +552,12 @@ impl RunPhase {
     };
     // Create the command
     let mut cmd = ProcessCommand::new(shell);
-    cmd.arg("-c").arg(&command.script);
+    // Positional arguments become $1..$n, with $0 naming the command
+    cmd
+      .arg("-c")
+      .arg(&command.script)
+      .arg(format!("run-{}", command.name()))
+      .args(positional_args);
     if let Some(current_dir) = current_dir {
       cmd.current_dir(current_dir);
     }
@@ -192,27 +565,42 @@ impl RunPhase {
     for (key, value) in env_vars {
       cmd.env(&key, &value);
     }
+    cmd
+  }
+  fn execute_script(
+    &self,
+    command: &Command,
+    positional_args: &[String],
+    env_vars: HashMap<String, String>,
+    mode: OutputMode,
+    current_dir: Option<&Path>,
+  ) -> Result<Option<Output>> {
+    let mut cmd = self.script_command(command, positional_args, env_vars, current_dir);
     // Execute based on mode
     match mode {
       OutputMode::Inherit => {
-        cmd.stdout(Stdio::inherit()).stderr(Stdio::inherit());
-        let status = cmd.status()?;
+        let status = process::status(&mut cmd, self.grace_period)?;
         if !status.success() {
-          return Err(anyhow!(
-            "Command failed with exit code: {}",
-            status.code().unwrap_or(-1)
-          ));
+          return Err(
+            ScriptError {
+              status,
+              output: None,
+            }
+            .into(),
+          );
         }
         Ok(None)
       }
       OutputMode::Capture => {
-        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
-        let output = cmd.output()?;
+        let output = process::output(&mut cmd, self.grace_period)?;
         if !output.status.success() {
-          return Err(anyhow!(
-            "Command failed with exit code: {}",
-            output.status.code().unwrap_or(-1)
-          ));
+          return Err(
+            ScriptError {
+              status: output.status,
+              output: Some(output),
+            }
+            .into(),
+          );
         }
         Ok(Some(output))
       }
@@ -220,10 +608,136 @@ impl RunPhase {
   }
 }
 
+/// Error for a long flag the command doesn't declare, suggesting the closest long flag
+fn unknown_flag(command: &Command, long: &str) -> anyhow::Error {
+  let mut message = format!("Unknown flag: --{}\n", long);
+  let longs = command.flags.iter().map(|flag| flag.long.as_str());
+  if let Some(suggestion) = suggest::closest(long, longs) {
+    message.push_str(&format!("\nDid you mean --{}?", suggestion));
+  }
+  see_flags(command, message)
+}
+
+/// Error for a short flag the command doesn't declare in the bundle `short`
+///
+/// A bundle that is a mistyped long flag, like `-release`, suggests that long flag, and a short flag in
+/// the wrong case suggests the declared one. Otherwise the declared short flags are listed.
+fn unknown_short_flag(command: &Command, short_char: char, short: &str) -> anyhow::Error {
+  let mut message = format!("Unknown short flag: -{}\n", short_char);
+  let longs = command.flags.iter().map(|flag| flag.long.as_str());
+  let shorts: Vec<char> = command.flags.iter().filter_map(|flag| flag.short).collect();
+  if let Some(suggestion) = suggest::closest(short, longs).filter(|_| short.chars().count() > 1) {
+    message.push_str(&format!("\nDid you mean --{}?", suggestion));
+  } else if let Some(suggestion) = shorts
+    .iter()
+    .find(|short| short.to_lowercase().eq(short_char.to_lowercase()))
+  {
+    message.push_str(&format!("\nDid you mean -{}?", suggestion));
+  } else if !shorts.is_empty() {
+    let shorts: Vec<String> = shorts.iter().map(|short| format!("-{}", short)).collect();
+    message.push_str(&format!("\nShort flags: {}", shorts.join(", ")));
+  }
+  see_flags(command, message)
+}
+
+/// `message` followed by how to see the command's flags
+fn see_flags(command: &Command, mut message: String) -> anyhow::Error {
+  if command.wants_help(&["--help".to_string()]) {
+    message.push_str(&format!(
+      "\nRun `run {} --help` to see its flags",
+      command.name()
+    ));
+  } else {
+    message.push_str(&format!(
+      "\nRun `run help {}` to see its flags",
+      command.name()
+    ));
+  }
+  anyhow!(message)
+}
+
+/// Argument that receives the positional value at `index`; every value from the varargs position on
+/// belongs to the varargs
+fn arg_at(command: &Command, index: usize) -> Option<&Argument> {
+  let varargs_position = command.args.iter().position(|arg| arg.is_varargs);
+  let index = varargs_position
+    .filter(|&pos| index >= pos)
+    .unwrap_or(index);
+  command.args.get(index)
+}
+
+/// Check a value against the choices of an argument or flag, if it has any
+pub(super) fn validate_choice(name: &str, choices: Option<Vec<&str>>, value: &str) -> Result<()> {
+  match choices {
+    Some(choices) if !choices.contains(&value) => Err(anyhow!(
+      "Invalid value '{}' for {}: expected one of {}",
+      value,
+      name,
+      choices.join(", ")
+    )),
+    _ => Ok(()),
+  }
+}
+
+/// Check a value against the built-in type of an argument or flag
+pub(super) fn validate_type(name: &str, value_type: &ValueType, value: &str, current_dir: Option<&Path>) -> Result<()> {
+  let path = current_dir.map_or_else(|| Path::new(value).to_path_buf(), |dir| dir.join(value));
+  let expected = match value_type {
+    ValueType::Int if value.parse::<i64>().is_err() => "an integer".to_string(),
+    ValueType::Float if value.parse::<f64>().is_err() => "a number".to_string(),
+    ValueType::Bool if !["true", "false", "yes", "no", "1", "0"].contains(&value) => "true or false".to_string(),
+    ValueType::Port if !value.parse::<u16>().is_ok_and(|port| port != 0) => "a port number (1-65535)".to_string(),
+    ValueType::Path if value.is_empty() => "a path".to_string(),
+    ValueType::File if !path.is_file() => "an existing file".to_string(),
+    ValueType::Dir if !path.is_dir() => "an existing directory".to_string(),
+    ValueType::Url if !Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*://[^\s/?#]+\S*$")?.is_match(value) => "a URL".to_string(),
+    ValueType::Regex(pattern) => {
+      let regex = Regex::new(&format!("^(?:{})$", pattern))
+        .map_err(|err| anyhow!("Invalid regex in type of {}: {}", name, err))?;
+      if regex.is_match(value) {
+        return Ok(());
+      }
+      format!("a value matching {}", pattern)
+    }
+    _ => return Ok(()),
+  };
+  Err(anyhow!(
+    "Invalid value '{}' for {}: expected {}",
+    value,
+    name,
+    expected
+  ))
+}
+
+/// Join values into a single string that the shell parses back into the same words
+///
+/// Values made only of characters that are never special to the shell are left bare, so simple
+/// lists still work with unquoted expansion; anything else is single-quoted.
+fn shell_join(values: &[String]) -> String {
+  values
+    .iter()
+    .map(|value| shell_quote(value))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+fn shell_quote(value: &str) -> String {
+  let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c);
+  if !value.is_empty() && value.chars().all(is_safe) {
+    value.to_string()
+  } else {
+    format!("'{}'", value.replace('\'', "'\\''"))
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::phases::parse::{Argument, Command, Flag};
+  use crate::phases::{
+    diagnostic::Span,
+    parse::{Argument, Command, Flag},
+    resolve::PlanStep,
+  };
 
   #[test]
   fn test_parse_cli_args() {
@@ -237,13 +751,19 @@ mod tests {
           name: "arg1".to_string(),
           optional: false,
           is_varargs: false,
+          type_hint: None,
+          default: None,
           description: None,
+          span: Span::default(),
         },
         Argument {
           name: "arg2".to_string(),
           optional: true,
           is_varargs: false,
+          type_hint: None,
+          default: None,
           description: None,
+          span: Span::default(),
         },
       ],
       flags: vec![
@@ -252,18 +772,26 @@ mod tests {
           long: "release".to_string(),
           takes_value: false,
           type_hint: None,
+          default: None,
           description: None,
+          span: Span::default(),
         },
         Flag {
           short: None,
           long: "debug".to_string(),
           takes_value: false,
           type_hint: None,
+          default: None,
           description: None,
+          span: Span::default(),
         },
       ],
       script: "echo test".to_string(),
       shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
     };
 
     let cli_args = vec![
@@ -292,10 +820,16 @@ mod tests {
         long: "release".to_string(),
         takes_value: false,
         type_hint: None,
+        default: None,
         description: None,
+        span: Span::default(),
       }],
       script: "echo test".to_string(),
       shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
     };
 
     let cli_args = vec!["-r".to_string()];
@@ -317,6 +851,10 @@ mod tests {
       flags: vec![],
       script: "echo test".to_string(),
       shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
     };
 
     let cli_args = vec!["--unknown".to_string()];
@@ -325,6 +863,176 @@ mod tests {
     assert!(result.unwrap_err().to_string().contains("Unknown flag"));
   }
 
+  #[test]
+  fn test_parse_cli_args_choices() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["deploy".to_string()],
+      description: None,
+      group: None,
+      args: vec![
+        Argument {
+          name: "target".to_string(),
+          optional: false,
+          is_varargs: false,
+          type_hint: Some("web|worker".to_string()),
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+        Argument {
+          name: "regions".to_string(),
+          optional: true,
+          is_varargs: true,
+          type_hint: Some("eu|us".to_string()),
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+      ],
+      flags: vec![Flag {
+        short: Some('e'),
+        long: "env".to_string(),
+        takes_value: true,
+        type_hint: Some("dev|staging|prod".to_string()),
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      script: "echo deploy".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+    let parse = |args: &[&str]| run_phase.parse_cli_args(&command, args.iter().map(|arg| arg.to_string()).collect());
+
+    assert!(parse(&["web", "eu", "us", "--env", "staging"]).is_ok());
+    assert_eq!(
+      parse(&["db"]).unwrap_err().to_string(),
+      "Invalid value 'db' for target: expected one of web, worker"
+    );
+    assert_eq!(
+      parse(&["web", "eu", "asia"]).unwrap_err().to_string(),
+      "Invalid value 'asia' for regions: expected one of eu, us"
+    );
+    assert_eq!(
+      parse(&["web", "-equa"]).unwrap_err().to_string(),
+      "Invalid value 'qua' for --env: expected one of dev, staging, prod"
+    );
+  }
+
+  #[test]
+  fn test_validate_types() {
+    let run_phase = RunPhase::new();
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("config.toml"), "").unwrap();
+    let arg = |name: &str, type_hint: &str| Argument {
+      name: name.to_string(),
+      optional: true,
+      is_varargs: false,
+      type_hint: Some(type_hint.to_string()),
+      default: None,
+      description: None,
+      span: Span::default(),
+    };
+    let command = Command {
+      names: vec!["serve".to_string()],
+      description: None,
+      group: None,
+      args: vec![
+        arg("replicas", "int"),
+        arg("ratio", "float"),
+        arg("enabled", "bool"),
+        arg("config", "file"),
+        arg("root", "dir"),
+        arg("endpoint", "url"),
+        arg("tag", "regex:v[0-9]+"),
+        arg("label", "name"),
+      ],
+      flags: vec![Flag {
+        short: None,
+        long: "port".to_string(),
+        takes_value: true,
+        type_hint: Some("port".to_string()),
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      script: "echo serve".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+    let validate = |args: &[&str], port: Option<&str>| {
+      let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+      let flags = port
+        .map(|port| HashMap::from([("port".to_string(), port.to_string())]))
+        .unwrap_or_default();
+      run_phase
+        .validate_types(&command, &args, &flags, Some(temp_dir.path()))
+        .map_err(|err| err.to_string())
+    };
+
+    let valid = [
+      "3",
+      "0.5",
+      "yes",
+      "config.toml",
+      ".",
+      "https://example.com/api",
+      "v12",
+      "anything",
+    ];
+    assert_eq!(validate(&valid, Some("8080")), Ok(()));
+    assert_eq!(
+      validate(&["abc"], None),
+      Err("Invalid value 'abc' for replicas: expected an integer".to_string())
+    );
+    assert_eq!(
+      validate(&["3", "fast"], None),
+      Err("Invalid value 'fast' for ratio: expected a number".to_string())
+    );
+    assert_eq!(
+      validate(&["3", "1", "maybe"], None),
+      Err("Invalid value 'maybe' for enabled: expected true or false".to_string())
+    );
+    assert_eq!(
+      validate(&["3", "1", "true", "missing.toml"], None),
+      Err("Invalid value 'missing.toml' for config: expected an existing file".to_string())
+    );
+    assert_eq!(
+      validate(&["3", "1", "true", "config.toml", "config.toml"], None),
+      Err("Invalid value 'config.toml' for root: expected an existing directory".to_string())
+    );
+    assert_eq!(
+      validate(&["3", "1", "true", "config.toml", ".", "example.com"], None),
+      Err("Invalid value 'example.com' for endpoint: expected a URL".to_string())
+    );
+    assert_eq!(
+      validate(
+        &[
+          "3",
+          "1",
+          "true",
+          "config.toml",
+          ".",
+          "http://localhost",
+          "v1x"
+        ],
+        None
+      ),
+      Err("Invalid value 'v1x' for tag: expected a value matching v[0-9]+".to_string())
+    );
+    assert_eq!(
+      validate(&[], Some("70000")),
+      Err("Invalid value '70000' for --port: expected a port number (1-65535)".to_string())
+    );
+  }
+
   #[test]
   fn test_validate_required_args() {
     let run_phase = RunPhase::new();
@@ -337,18 +1045,28 @@ mod tests {
           name: "required".to_string(),
           optional: false,
           is_varargs: false,
+          type_hint: None,
+          default: None,
           description: None,
+          span: Span::default(),
         },
         Argument {
           name: "optional".to_string(),
           optional: true,
           is_varargs: false,
+          type_hint: None,
+          default: None,
           description: None,
+          span: Span::default(),
         },
       ],
       flags: vec![],
       script: "echo test".to_string(),
       shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
     };
 
     // Should pass with required arg provided
@@ -380,10 +1098,16 @@ mod tests {
         long: "per-crate".to_string(),
         takes_value: false,
         type_hint: None,
+        default: None,
         description: None,
+        span: Span::default(),
       }],
       script: "echo \"$per_crate|$PER_CRATE\"".to_string(),
       shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
     };
 
     let output = run_phase
@@ -411,10 +1135,16 @@ mod tests {
         long: "per-crate".to_string(),
         takes_value: true,
         type_hint: None,
+        default: None,
         description: None,
+        span: Span::default(),
       }],
       script: "echo \"$per_crate|$PER_CRATE\"".to_string(),
       shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
     };
 
     let output = run_phase
@@ -428,4 +1158,742 @@ mod tests {
     let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
     assert_eq!(stdout, "--per-crate=fast|fast");
   }
+
+  #[test]
+  fn test_defaults_applied_when_absent() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["greet".to_string()],
+      description: None,
+      group: None,
+      args: vec![Argument {
+        name: "name".to_string(),
+        optional: true,
+        is_varargs: false,
+        type_hint: None,
+        default: Some("World".to_string()),
+        description: None,
+        span: Span::default(),
+      }],
+      flags: vec![Flag {
+        short: None,
+        long: "mode".to_string(),
+        takes_value: true,
+        type_hint: None,
+        default: Some("fast".to_string()),
+        description: None,
+        span: Span::default(),
+      }],
+      script: "echo \"$1|$name|$mode|$MODE\"".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let output = run_phase
+      .run(command.clone(), vec![], OutputMode::Capture)
+      .unwrap()
+      .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    assert_eq!(stdout, "World|World|--mode=fast|fast");
+
+    let output = run_phase
+      .run(
+        command,
+        vec!["Ferris".to_string(), "--mode=slow".to_string()],
+        OutputMode::Capture,
+      )
+      .unwrap()
+      .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    assert_eq!(stdout, "Ferris|Ferris|--mode=slow|slow");
+  }
+
+  #[test]
+  fn test_defaults_after_optional_argument_without_one() {
+    let run_phase = RunPhase::new();
+    let argument = |name: &str, default: Option<&str>| Argument {
+      name: name.to_string(),
+      optional: true,
+      is_varargs: false,
+      type_hint: None,
+      default: default.map(str::to_string),
+      description: None,
+      span: Span::default(),
+    };
+    let command = Command {
+      names: vec!["g".to_string()],
+      description: None,
+      group: None,
+      args: vec![argument("a", None), argument("b", Some("x"))],
+      flags: vec![],
+      script: "echo \"$#|$a|$b\"".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    // $2 can't be set without $1, but $b still gets its default
+    let output = run_phase
+      .run(command, vec![], OutputMode::Capture)
+      .unwrap()
+      .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    assert_eq!(stdout, "0||x");
+  }
+
+  #[test]
+  fn test_script_exit_code_is_preserved() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["fail".to_string()],
+      description: None,
+      group: None,
+      args: vec![],
+      flags: vec![],
+      script: "echo partial; exit 42".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let err = run_phase
+      .run(command, vec![], OutputMode::Capture)
+      .unwrap_err();
+    let failure = err.downcast_ref::<ScriptError>().unwrap();
+    assert_eq!(failure.code(), Some(42));
+    assert_eq!(failure.exit_code(), 42);
+    assert_eq!(err.to_string(), "Command failed with exit code: 42");
+    let stdout = String::from_utf8_lossy(&failure.output.as_ref().unwrap().stdout).to_string();
+    assert_eq!(stdout.trim(), "partial");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_script_signal_maps_to_exit_code() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["killed".to_string()],
+      description: None,
+      group: None,
+      args: vec![],
+      flags: vec![],
+      script: "kill -TERM $$".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let err = run_phase
+      .run(command, vec![], OutputMode::Capture)
+      .unwrap_err();
+    let failure = err.downcast_ref::<ScriptError>().unwrap();
+    assert_eq!(failure.code(), None);
+    assert_eq!(failure.signal(), Some(15));
+    assert_eq!(failure.exit_code(), 143);
+    assert_eq!(err.to_string(), "Command terminated by signal: 15");
+  }
+
+  #[test]
+  fn test_positional_parameters() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["t".to_string(), "test".to_string()],
+      description: None,
+      group: None,
+      args: vec![
+        Argument {
+          name: "target".to_string(),
+          optional: false,
+          is_varargs: false,
+          type_hint: None,
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+        Argument {
+          name: "args".to_string(),
+          optional: true,
+          is_varargs: true,
+          type_hint: None,
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+      ],
+      flags: vec![],
+      script: "printf '%s|' \"$0\" \"$#\" \"$@\"".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let output = run_phase
+      .run(
+        command,
+        vec!["unit".to_string(), "a b".to_string(), "c".to_string()],
+        OutputMode::Capture,
+      )
+      .unwrap()
+      .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    assert_eq!(stdout, "run-test|3|unit|a b|c|");
+  }
+
+  #[test]
+  fn test_shell_quote() {
+    assert_eq!(shell_quote("--release"), "--release");
+    assert_eq!(shell_quote("src/main.rs"), "src/main.rs");
+    assert_eq!(shell_quote("my test"), "'my test'");
+    assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    assert_eq!(shell_quote("$HOME"), "'$HOME'");
+    assert_eq!(shell_quote(""), "''");
+  }
+
+  #[test]
+  fn test_varargs_env_vars() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["test".to_string()],
+      description: None,
+      group: None,
+      args: vec![Argument {
+        name: "args".to_string(),
+        optional: true,
+        is_varargs: true,
+        type_hint: None,
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      flags: vec![],
+      script: "echo \"$args\"; echo \"$ARGS_COUNT|$ARGS_0|$ARGS_1\"; eval \"set -- $args\"; echo \"$#|$2\"".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let output = run_phase
+      .run(
+        command,
+        vec!["filter".to_string(), "my test".to_string()],
+        OutputMode::Capture,
+      )
+      .unwrap()
+      .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    assert_eq!(stdout, "filter 'my test'\n2|filter|my test\n2|my test\n");
+  }
+
+  #[test]
+  fn test_varargs_env_vars_empty() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["test".to_string()],
+      description: None,
+      group: None,
+      args: vec![Argument {
+        name: "args".to_string(),
+        optional: true,
+        is_varargs: true,
+        type_hint: None,
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      flags: vec![],
+      script: "echo \"${args-unset}|$ARGS_COUNT\"".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let output = run_phase
+      .run(command, vec![], OutputMode::Capture)
+      .unwrap()
+      .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    assert_eq!(stdout, "unset|0");
+  }
+
+  #[test]
+  fn test_parse_cli_args_double_dash() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["test".to_string()],
+      description: None,
+      group: None,
+      args: vec![Argument {
+        name: "args".to_string(),
+        optional: true,
+        is_varargs: true,
+        type_hint: None,
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      flags: vec![Flag {
+        short: Some('r'),
+        long: "release".to_string(),
+        takes_value: false,
+        type_hint: None,
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      script: "echo test".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let cli_args = vec![
+      "-r".to_string(),
+      "--".to_string(),
+      "--nocapture".to_string(),
+      "-r".to_string(),
+      "--".to_string(),
+    ];
+    let (args, flags, _) = run_phase.parse_cli_args(&command, cli_args).unwrap();
+
+    assert_eq!(args, vec!["--nocapture", "-r", "--"]);
+    assert!(flags.contains("release"));
+  }
+
+  #[test]
+  fn test_parse_cli_args_passthrough() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["test".to_string()],
+      description: None,
+      group: None,
+      args: vec![
+        Argument {
+          name: "target".to_string(),
+          optional: false,
+          is_varargs: false,
+          type_hint: None,
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+        Argument {
+          name: "args".to_string(),
+          optional: true,
+          is_varargs: true,
+          type_hint: None,
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+      ],
+      flags: vec![Flag {
+        short: Some('r'),
+        long: "release".to_string(),
+        takes_value: false,
+        type_hint: None,
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      script: "echo test".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: true,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    // Unknown flags before the varargs position are still rejected
+    let result = run_phase.parse_cli_args(&command, vec!["--nocapture".to_string()]);
+    assert!(
+      result
+        .unwrap_err()
+        .to_string()
+        .contains("Unknown flag: --nocapture")
+    );
+
+    let cli_args = vec![
+      "unit".to_string(),
+      "--nocapture".to_string(),
+      "-q".to_string(),
+      "--test-threads=1".to_string(),
+      "--release".to_string(),
+    ];
+    let (args, flags, _) = run_phase.parse_cli_args(&command, cli_args).unwrap();
+
+    assert_eq!(args, vec!["unit", "--nocapture", "-q", "--test-threads=1"]);
+    assert!(flags.contains("release"));
+  }
+
+  #[test]
+  fn test_parse_cli_args_value_flag_forms() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["build".to_string()],
+      description: None,
+      group: None,
+      args: vec![Argument {
+        name: "target".to_string(),
+        optional: true,
+        is_varargs: false,
+        type_hint: None,
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      flags: vec![Flag {
+        short: Some('o'),
+        long: "out".to_string(),
+        takes_value: true,
+        type_hint: None,
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      script: "echo test".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let forms: Vec<Vec<&str>> = vec![
+      vec!["--out", "x"],
+      vec!["--out=x"],
+      vec!["-o", "x"],
+      vec!["-ox"],
+      vec!["-o=x"],
+    ];
+    for form in forms {
+      let cli_args = form.iter().map(|s| s.to_string()).collect();
+      let (args, _, flag_values) = run_phase.parse_cli_args(&command, cli_args).unwrap();
+      assert!(args.is_empty(), "unexpected positional args for {:?}", form);
+      assert_eq!(
+        flag_values.get("out"),
+        Some(&"x".to_string()),
+        "wrong value for {:?}",
+        form
+      );
+    }
+
+    // A value that looks like a flag is taken verbatim when it follows the flag
+    let (_, _, flag_values) = run_phase
+      .parse_cli_args(&command, vec!["--out".to_string(), "-".to_string()])
+      .unwrap();
+    assert_eq!(flag_values.get("out"), Some(&"-".to_string()));
+  }
+
+  #[test]
+  fn test_parse_cli_args_value_flag_errors() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["build".to_string()],
+      description: None,
+      group: None,
+      args: vec![],
+      flags: vec![
+        Flag {
+          short: Some('o'),
+          long: "out".to_string(),
+          takes_value: true,
+          type_hint: None,
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+        Flag {
+          short: Some('r'),
+          long: "release".to_string(),
+          takes_value: false,
+          type_hint: None,
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+      ],
+      script: "echo test".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let error = |cli_args: &[&str]| {
+      let cli_args = cli_args.iter().map(|s| s.to_string()).collect();
+      run_phase
+        .parse_cli_args(&command, cli_args)
+        .unwrap_err()
+        .to_string()
+    };
+    assert_eq!(error(&["--out"]), "Flag --out requires a value");
+    assert_eq!(error(&["-o"]), "Flag -o requires a value");
+    assert_eq!(
+      error(&["--release=yes"]),
+      "Flag --release does not take a value"
+    );
+    assert_eq!(
+      error(&["--output=x"]),
+      "Unknown flag: --output\n\nRun `run build --help` to see its flags"
+    );
+    assert_eq!(
+      error(&["--relase"]),
+      "Unknown flag: --relase\n\nDid you mean --release?\nRun `run build --help` to see its flags"
+    );
+    assert_eq!(
+      error(&["-x"]),
+      "Unknown short flag: -x\n\nShort flags: -o, -r\nRun `run build --help` to see its flags"
+    );
+    assert_eq!(
+      error(&["-rR"]),
+      "Unknown short flag: -R\n\nDid you mean -r?\nRun `run build --help` to see its flags"
+    );
+    assert_eq!(
+      error(&["-relase"]),
+      "Unknown short flag: -e\n\nDid you mean --release?\nRun `run build --help` to see its flags"
+    );
+  }
+
+  #[test]
+  fn test_parse_cli_args_negative_number() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["scale".to_string()],
+      description: None,
+      group: None,
+      args: vec![Argument {
+        name: "amount".to_string(),
+        optional: false,
+        is_varargs: false,
+        type_hint: None,
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      flags: vec![],
+      script: "echo test".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let (args, _, _) = run_phase
+      .parse_cli_args(&command, vec!["-2.5".to_string()])
+      .unwrap();
+    assert_eq!(args, vec!["-2.5"]);
+  }
+
+  #[test]
+  fn test_parse_cli_args_bundled_short_flags() {
+    let run_phase = RunPhase::new();
+    let command = Command {
+      names: vec!["build".to_string()],
+      description: None,
+      group: None,
+      args: vec![Argument {
+        name: "target".to_string(),
+        optional: true,
+        is_varargs: false,
+        type_hint: None,
+        default: None,
+        description: None,
+        span: Span::default(),
+      }],
+      flags: vec![
+        Flag {
+          short: Some('r'),
+          long: "release".to_string(),
+          takes_value: false,
+          type_hint: None,
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+        Flag {
+          short: Some('v'),
+          long: "verbose".to_string(),
+          takes_value: false,
+          type_hint: None,
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+        Flag {
+          short: Some('o'),
+          long: "out".to_string(),
+          takes_value: true,
+          type_hint: None,
+          default: None,
+          description: None,
+          span: Span::default(),
+        },
+      ],
+      script: "echo test".to_string(),
+      shebang: "#!/bin/sh".to_string(),
+      passthrough: false,
+      depends: vec![],
+      private: false,
+      span: Span::default(),
+    };
+
+    let (args, flags, flag_values) = run_phase
+      .parse_cli_args(&command, vec!["-rv".to_string(), "app".to_string()])
+      .unwrap();
+    assert_eq!(args, vec!["app"]);
+    assert!(flags.contains("release"));
+    assert!(flags.contains("verbose"));
+    assert!(flag_values.is_empty());
+
+    let (args, flags, flag_values) = run_phase
+      .parse_cli_args(&command, vec!["-rvo".to_string(), "dist".to_string()])
+      .unwrap();
+    assert!(args.is_empty());
+    assert_eq!(flags.len(), 2);
+    assert_eq!(flag_values.get("out"), Some(&"dist".to_string()));
+
+    let (_, flags, flag_values) = run_phase
+      .parse_cli_args(&command, vec!["-vodist".to_string()])
+      .unwrap();
+    assert!(flags.contains("verbose"));
+    assert_eq!(flag_values.get("out"), Some(&"dist".to_string()));
+  }
+
+  /// Writer whose output stays readable after it is handed to `ParallelOutput`
+  #[derive(Clone, Default)]
+  struct SharedBuffer(std::sync::Arc<Mutex<Vec<u8>>>);
+
+  impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  impl SharedBuffer {
+    fn lines(&self) -> Vec<String> {
+      String::from_utf8_lossy(&self.0.lock().unwrap())
+        .lines()
+        .map(String::from)
+        .collect()
+    }
+  }
+
+  fn plan_step(name: &str, script: &str, dependencies: Vec<usize>) -> PlanStep {
+    PlanStep {
+      command: Command {
+        names: vec![name.to_string()],
+        description: None,
+        group: None,
+        args: vec![],
+        flags: vec![],
+        script: script.to_string(),
+        shebang: "#!/bin/sh".to_string(),
+        passthrough: false,
+        depends: vec![],
+        private: false,
+        span: Span::default(),
+      },
+      dependencies,
+    }
+  }
+
+  fn run_parallel(steps: Vec<PlanStep>, keep_going: bool) -> (Result<()>, Vec<String>, Vec<String>) {
+    let (stdout, stderr) = (SharedBuffer::default(), SharedBuffer::default());
+    let output = ParallelOutput {
+      stdout: Mutex::new(Box::new(stdout.clone())),
+      stderr: Mutex::new(Box::new(stderr.clone())),
+      colors: false,
+    };
+    let parallelism = Parallelism {
+      jobs: 4,
+      keep_going,
+    };
+    let result = RunPhase::with_grace_period(Duration::from_secs(1)).run_parallel(
+      ExecutionPlan { steps },
+      vec!["target-arg".to_string()],
+      parallelism,
+      None,
+      &output,
+    );
+    (result, stdout.lines(), stderr.lines())
+  }
+
+  #[test]
+  fn test_run_parallel_prefixes_output() {
+    let steps = vec![
+      plan_step("fmt", "echo formatted", vec![]),
+      plan_step("check", "echo checked; echo warning >&2", vec![0]),
+      plan_step("clippy", "echo linted", vec![0]),
+      plan_step("verify", "echo \"verified $1\"", vec![1, 2]),
+    ];
+
+    let (result, mut stdout, stderr) = run_parallel(steps, false);
+    assert!(result.is_ok());
+    // check and clippy may finish in either order, but always between fmt and verify
+    stdout[1..3].sort();
+    assert_eq!(
+      stdout,
+      vec![
+        "fmt    | formatted",
+        "check  | checked",
+        "clippy | linted",
+        "verify | verified target-arg",
+      ]
+    );
+    assert_eq!(stderr, vec!["check  | warning"]);
+  }
+
+  #[test]
+  fn test_run_parallel_failure_cancels_siblings() {
+    let steps = vec![
+      plan_step("serve", "sleep 10; echo served", vec![]),
+      plan_step("lint", "exit 3", vec![]),
+      plan_step("verify", "echo verified", vec![0, 1]),
+    ];
+
+    let started = std::time::Instant::now();
+    let (result, stdout, _) = run_parallel(steps, false);
+    let err = result.unwrap_err();
+    assert_eq!(err.downcast_ref::<ScriptError>().unwrap().exit_code(), 3);
+    assert!(stdout.is_empty(), "unexpected output: {:?}", stdout);
+    assert!(started.elapsed() < Duration::from_secs(8));
+  }
+
+  #[test]
+  fn test_run_parallel_keep_going() {
+    let steps = vec![
+      plan_step("lint", "exit 3", vec![]),
+      plan_step("build", "sleep 0.2; echo built", vec![]),
+      plan_step("package", "echo packaged", vec![1]),
+      plan_step("verify", "echo verified", vec![0, 2]),
+    ];
+
+    let (result, stdout, _) = run_parallel(steps, true);
+    let err = result.unwrap_err();
+    assert_eq!(err.downcast_ref::<ScriptError>().unwrap().exit_code(), 3);
+    // Everything not depending on lint still runs
+    assert_eq!(stdout, vec!["build   | built", "package | packaged"]);
+  }
 }
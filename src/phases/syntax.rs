@@ -0,0 +1,421 @@
+//! A lossless syntax tree of a Runfile
+//!
+//! Where tokens keep only what the Runfile means, the syntax tree keeps every line as written, with its
+//! indentation, comments and line ending, grouped the way [`TokenizePhase`] reads them. Printing the tree
+//! gives back the Runfile byte for byte, so tools can change some lines and leave the rest untouched.
+//!
+//! Each line gets the kind of the token the tokenizer reads it into, with the grammar the Runfile asks for,
+//! so the tree and the tokens can't disagree. Lines that can't be read, like a parameter declaration that
+//! isn't one, are kept where they are; reading them is the tokenizer's job.
+
+use std::fmt;
+
+use super::{diagnostic::Span, tokenize::TokenizePhase};
+
+/// A Runfile as written
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyntaxTree {
+  pub items: Vec<Item>,
+  /// Whether the lines were read with the legacy grammar, which tools that rewrite them don't support
+  pub legacy_grammar: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+  /// A line outside of any command or group header: a blank line, a comment that doesn't describe a
+  /// command, an `@set` line or an attribute that isn't above a command
+  Line(Line),
+  /// `# ---`, `# Name`, `# ---`
+  GroupHeader([Line; 3]),
+  Command(CommandNode),
+}
+
+/// A command with the lines that belong to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandNode {
+  /// Description comments and attributes directly above the command line
+  pub leading: Vec<Line>,
+  /// The command line, with its names and any inline arguments and flags
+  pub header: Line,
+  /// Argument and flag declarations below the command line, with the comments and blank lines between
+  /// them
+  pub parameters: Vec<Line>,
+  /// The script, with the comments and blank lines between its lines
+  pub script: Vec<Line>,
+}
+
+/// One line of a Runfile
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+  pub kind: LineKind,
+  /// The line without its line ending
+  pub text: String,
+  /// `"\n"`, `"\r\n"`, or nothing for a last line without one
+  pub ending: String,
+  /// Where the line was in the Runfile the tree was read from
+  pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+  Blank,
+  Comment,
+  /// A `# ---` line of a group header
+  GroupSeparator,
+  /// The `# Name` line of a group header
+  GroupName,
+  Attribute,
+  /// The line a command starts with
+  Command,
+  Parameter,
+  Script,
+}
+
+impl SyntaxTree {
+  /// Read a Runfile with the grammar it asks for
+  pub fn parse(content: &str) -> Self {
+    Self::parse_with(content, &TokenizePhase::new())
+  }
+  /// Read a Runfile with the grammar `tokenizer` would read it with
+  pub fn parse_with(content: &str, tokenizer: &TokenizePhase) -> Self {
+    TreeBuilder::default().build(content, &tokenizer.grammar_for(content))
+  }
+  /// Every line of the Runfile, in order
+  pub fn lines(&self) -> impl Iterator<Item = &Line> {
+    self.items.iter().flat_map(Item::lines)
+  }
+  pub fn commands(&self) -> impl Iterator<Item = &CommandNode> {
+    self.items.iter().filter_map(|item| match item {
+      Item::Command(command) => Some(command),
+      _ => None,
+    })
+  }
+  pub fn commands_mut(&mut self) -> impl Iterator<Item = &mut CommandNode> {
+    self.items.iter_mut().filter_map(|item| match item {
+      Item::Command(command) => Some(command),
+      _ => None,
+    })
+  }
+}
+
+/// Prints the Runfile back, exactly as it was if nothing was changed
+impl fmt::Display for SyntaxTree {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for line in self.lines() {
+      write!(f, "{}{}", line.text, line.ending)?;
+    }
+    Ok(())
+  }
+}
+
+impl Item {
+  pub fn lines(&self) -> Box<dyn Iterator<Item = &Line> + '_> {
+    match self {
+      Item::Line(line) => Box::new(std::iter::once(line)),
+      Item::GroupHeader(lines) => Box::new(lines.iter()),
+      Item::Command(command) => Box::new(command.lines()),
+    }
+  }
+}
+
+impl CommandNode {
+  /// Every line of the command, in order
+  pub fn lines(&self) -> impl Iterator<Item = &Line> {
+    self
+      .leading
+      .iter()
+      .chain(std::iter::once(&self.header))
+      .chain(&self.parameters)
+      .chain(&self.script)
+  }
+  /// The comment lines describing the command
+  pub fn description(&self) -> impl Iterator<Item = &Line> {
+    self
+      .leading
+      .iter()
+      .filter(|line| line.kind == LineKind::Comment)
+  }
+  pub fn attributes(&self) -> impl Iterator<Item = &Line> {
+    self
+      .leading
+      .iter()
+      .filter(|line| line.kind == LineKind::Attribute)
+  }
+}
+
+impl Line {
+  /// The spaces and tabs the line starts with
+  pub fn indent(&self) -> &str {
+    &self.text[..self.text.len() - self.text.trim_start().len()]
+  }
+  /// The line without its indentation and trailing whitespace
+  pub fn content(&self) -> &str {
+    self.text.trim()
+  }
+  pub fn is_indented(&self) -> bool {
+    !self.indent().is_empty()
+  }
+}
+
+/// Reads lines into items, holding back the lines after a command until it's clear whether they belong to
+/// its body, describe the next command, or stand on their own
+#[derive(Default)]
+struct TreeBuilder {
+  items: Vec<Item>,
+  command: Option<CommandNode>,
+  /// Blank lines, comments and attributes seen since the last line with a place
+  held: Vec<Line>,
+}
+
+impl TreeBuilder {
+  /// Group the lines by the kinds the tokenizer reads them as, so the tree can't read a line differently
+  fn build(mut self, content: &str, tokenizer: &TokenizePhase) -> SyntaxTree {
+    let kinds = tokenizer.line_kinds(content);
+    let kind_at = |index: usize| kinds.get(&(index + 1)).copied();
+    let lines = split_lines(content);
+    let mut i = 0;
+    while i < lines.len() {
+      let mut line = lines[i].clone();
+      if kind_at(i + 1) == Some(LineKind::GroupName) && i + 2 < lines.len() {
+        self.close_command();
+        self.release_held();
+        let mut header = [line, lines[i + 1].clone(), lines[i + 2].clone()];
+        header[0].kind = LineKind::GroupSeparator;
+        header[1].kind = LineKind::GroupName;
+        header[2].kind = LineKind::GroupSeparator;
+        self.items.push(Item::GroupHeader(header));
+        i += 3;
+        continue;
+      }
+      let trimmed = line.content();
+      let kind = kind_at(i);
+      // Lines without a kind were skipped or couldn't be read, and go where they'd be if they could
+      if trimmed.is_empty() {
+        line.kind = LineKind::Blank;
+        self.held.push(line);
+      } else if kind == Some(LineKind::Attribute) || (kind.is_none() && !line.is_indented() && trimmed.starts_with('@'))
+      {
+        // An attribute belongs to the command below it, so the one above is over
+        self.close_command();
+        line.kind = LineKind::Attribute;
+        self.held.push(line);
+      } else if !line.is_indented() && trimmed.starts_with('#') && kind != Some(LineKind::Script) {
+        line.kind = LineKind::Comment;
+        self.held.push(line);
+      } else if kind == Some(LineKind::Command) || (kind.is_none() && !line.is_indented()) {
+        self.close_command();
+        // The comments and attributes right above the command line are its own
+        let split = self
+          .held
+          .iter()
+          .rposition(|line| line.kind == LineKind::Blank)
+          .map_or(0, |blank| blank + 1);
+        let leading = self.held.split_off(split);
+        self.release_held();
+        line.kind = LineKind::Command;
+        self.command = Some(CommandNode {
+          leading,
+          header: line,
+          parameters: Vec::new(),
+          script: Vec::new(),
+        });
+      } else {
+        // Only lines among the parameters are skipped or fail to read there
+        let parameter = matches!(kind, None | Some(LineKind::Parameter));
+        line.kind = kind.unwrap_or(if trimmed.starts_with('#') {
+          LineKind::Comment
+        } else {
+          LineKind::Parameter
+        });
+        self.body_line(line, parameter);
+      }
+      i += 1;
+    }
+    self.close_command();
+    self.release_held();
+    SyntaxTree {
+      items: self.items,
+      legacy_grammar: tokenizer.legacy_grammar,
+    }
+  }
+  /// Add a line below a command line to the command's parameters or script, along with the lines held
+  /// back before it
+  fn body_line(&mut self, line: Line, parameter: bool) {
+    let Some(command) = &mut self.command else {
+      // Lines before any command stand on their own
+      self.release_held();
+      self.items.push(Item::Line(Line {
+        kind: LineKind::Script,
+        ..line
+      }));
+      return;
+    };
+    // Once the script starts, everything after it is part of it
+    let body = if parameter && command.script.is_empty() {
+      &mut command.parameters
+    } else {
+      &mut command.script
+    };
+    body.append(&mut self.held);
+    body.push(line);
+  }
+  fn close_command(&mut self) {
+    if let Some(command) = self.command.take() {
+      self.items.push(Item::Command(command));
+    }
+  }
+  /// Lines held back stand on their own
+  fn release_held(&mut self) {
+    self.items.extend(self.held.drain(..).map(Item::Line));
+  }
+}
+
+/// Split the content into lines that keep their endings, for now all of them blank
+fn split_lines(content: &str) -> Vec<Line> {
+  let mut lines = Vec::new();
+  let mut start = 0;
+  for (index, raw) in content.split_inclusive('\n').enumerate() {
+    let text = raw
+      .strip_suffix('\n')
+      .map_or(raw, |text| text.strip_suffix('\r').unwrap_or(text));
+    lines.push(Line {
+      kind: LineKind::Blank,
+      text: text.to_string(),
+      ending: raw[text.len()..].to_string(),
+      span: Span::in_line(index + 1, start, raw, text),
+    });
+    start += raw.len();
+  }
+  lines
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const RUNFILE: &str = "@set prefix-matching\n\n# ---\n# Build\n# ---\n\n# Build the project\n# in any mode\n@depends fmt\nbuild\n  target    # What to build\n\n  # Faster builds\n  -r, --release:  # Optimize\n  cargo build $release\n\n\tcargo test\n\n# Not a description\n\nfmt:\r\n  cargo fmt  \r\n# A comment in the script\n  echo done";
+
+  #[test]
+  fn test_round_trip() {
+    for content in [
+      RUNFILE,
+      "",
+      "\n\n",
+      "build:\n  cargo build",
+      "  indented before any command\n:\n@\n  --x\n# ---\n# ---",
+    ] {
+      assert_eq!(SyntaxTree::parse(content).to_string(), content);
+    }
+  }
+
+  #[test]
+  fn test_structure() {
+    let tree = SyntaxTree::parse(RUNFILE);
+    let kinds: Vec<&str> = tree
+      .items
+      .iter()
+      .map(|item| match item {
+        Item::Line(_) => "line",
+        Item::GroupHeader(_) => "group",
+        Item::Command(_) => "command",
+      })
+      .collect();
+    assert_eq!(
+      kinds,
+      vec![
+        "line", "line", "group", "line", "command", "line", "line", "line", "command"
+      ]
+    );
+
+    let commands: Vec<&CommandNode> = tree.commands().collect();
+    let build = commands[0];
+    assert_eq!(build.header.text, "build");
+    let description: Vec<&str> = build.description().map(Line::content).collect();
+    assert_eq!(description, vec!["# Build the project", "# in any mode"]);
+    assert_eq!(build.attributes().count(), 1);
+    let parameters: Vec<LineKind> = build.parameters.iter().map(|line| line.kind).collect();
+    assert_eq!(
+      parameters,
+      vec![
+        LineKind::Parameter,
+        LineKind::Blank,
+        LineKind::Comment,
+        LineKind::Parameter
+      ]
+    );
+    // Blank lines between script lines belong to the script, those after it don't
+    assert_eq!(build.script.len(), 3);
+    assert_eq!(build.script[2].indent(), "\t");
+
+    let fmt = commands[1];
+    assert!(fmt.leading.is_empty());
+    assert_eq!(fmt.header.ending, "\r\n");
+    // An unindented comment followed by more script is part of it
+    assert_eq!(fmt.script.len(), 3);
+    assert_eq!(fmt.script[1].kind, LineKind::Comment);
+    assert_eq!(fmt.script[2].ending, "");
+  }
+
+  #[test]
+  fn test_lines_read_like_tokens() {
+    let kinds = |tree: &SyntaxTree| -> Vec<(Vec<LineKind>, Vec<LineKind>)> {
+      tree
+        .commands()
+        .map(|command| {
+          (
+            command.parameters.iter().map(|line| line.kind).collect(),
+            command.script.iter().map(|line| line.kind).collect(),
+          )
+        })
+        .collect()
+    };
+    let content = "build:\n  -r, --release\n  cargo build $release\n";
+    let tree = SyntaxTree::parse(content);
+    assert!(!tree.legacy_grammar);
+    assert_eq!(
+      kinds(&tree),
+      vec![(vec![], vec![LineKind::Script, LineKind::Script])]
+    );
+    // The Runfile's setting and the tokenizer's grammar both choose the legacy grammar
+    let legacy = format!("@set legacy-grammar\n{}", content);
+    for tree in [
+      SyntaxTree::parse(&legacy),
+      SyntaxTree::parse_with(content, &TokenizePhase::with_legacy_grammar(true)),
+    ] {
+      assert!(tree.legacy_grammar);
+      assert_eq!(
+        kinds(&tree),
+        vec![(vec![LineKind::Parameter], vec![LineKind::Script])]
+      );
+    }
+    // After a line that isn't a parameter, the tokenizer reads the rest as script
+    let tree = SyntaxTree::parse("build\n  # Options\n  not a parameter\n  cargo build\n");
+    assert_eq!(
+      kinds(&tree),
+      vec![(
+        vec![LineKind::Comment, LineKind::Parameter],
+        vec![LineKind::Script]
+      )]
+    );
+  }
+
+  #[test]
+  fn test_edit() {
+    let mut tree = SyntaxTree::parse(RUNFILE);
+    let fmt = tree.commands_mut().nth(1).unwrap();
+    fmt.header.text = "fmt, f:".to_string();
+    assert_eq!(tree.to_string(), RUNFILE.replace("fmt:\r\n", "fmt, f:\r\n"));
+  }
+
+  #[test]
+  fn test_spans() {
+    let content = "build:\r\n  cargo build\n";
+    let tree = SyntaxTree::parse(content);
+    let lines: Vec<&Line> = tree.lines().collect();
+    assert_eq!((lines[1].span.line, lines[1].span.column), (2, 1));
+    assert_eq!(
+      &content[lines[1].span.start..lines[1].span.end],
+      "  cargo build"
+    );
+  }
+}
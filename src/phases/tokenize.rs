@@ -1,12 +1,49 @@
-use anyhow::Result;
+//! Reading a Runfile into tokens, mostly one per line
+//!
+//! The grammar, line by line:
+//!
+//! - Blank lines are skipped.
+//! - `# ---`, `# Name`, `# ---`, with any number of dashes, is a group header.
+//! - Other unindented lines starting with `#` are comments. Those directly above a command, or above its
+//!   attributes, are its description.
+//! - Unindented lines starting with `@` are attributes, or settings with `@set`.
+//! - Any other unindented line is a command: its names, then any arguments and flags. If it ends with `:`,
+//!   its script starts on the next line.
+//! - Otherwise, the indented lines below it declare one argument or flag each, optionally followed by
+//!   `# description`. The first of them to end with `:` (before any description) is the last one.
+//!   Indented comments among them are skipped, and any other line is an error.
+//! - Every indented line after that is part of the script, as written.
+//!
+//! With `@set legacy-grammar`, indented lines are told apart by what they look like instead, as they
+//! used to be, and every line that the grammar above would read differently gets a warning. Without it,
+//! every line that the legacy grammar read differently gets a warning, so a Runfile written for an older
+//! version doesn't change its meaning unnoticed. Those that were a flag, or an argument the script uses,
+//! are `parameter-in-script` warnings, and the commands they're in won't run.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use anyhow::{Result, anyhow};
+use regex::Regex;
+
+use super::{
+  diagnostic::{Diagnostic, Diagnostics, Severity, Span, Spanned},
+  syntax::LineKind,
+};
 
 // Type aliases for complex return types
-type InlineArg = (String, bool, bool);
+type InlineArg = (String, bool, bool, Option<String>, Option<String>, Span);
 
-// (name, optional, is_varargs)
-type InlineFlag = (String, Option<char>, bool, Option<String>);
+// (name, optional, is_varargs, type_hint, default, span)
+type InlineFlag = (
+  String,
+  Option<char>,
+  bool,
+  Option<String>,
+  Option<String>,
+  Span,
+);
 
-// (name, short, takes_value, type_hint)
+// (name, short, takes_value, type_hint, default, span)
 type ArgsAndFlagsResult = (Vec<InlineArg>, Vec<InlineFlag>);
 
 #[derive(Debug, Clone, PartialEq)]
@@ -24,6 +61,8 @@ pub enum Token {
     name: String,
     optional: bool,
     is_varargs: bool,
+    type_hint: Option<String>,
+    default: Option<String>,
     comment: Option<String>,
   },
   Flag {
@@ -31,6 +70,7 @@ pub enum Token {
     short: Option<char>,
     takes_value: bool,
     type_hint: Option<String>,
+    default: Option<String>,
     comment: Option<String>,
   },
   ScriptLine {
@@ -39,25 +79,72 @@ pub enum Token {
   Comment {
     content: String,
   },
+  Attribute {
+    name: String,
+    args: Vec<String>,
+  },
+}
+
+/// A line of the Runfile, for giving spans to its parts
+struct Line<'a> {
+  text: &'a str,
+  /// Line number, starting at 1
+  number: usize,
+  /// Byte offset of the line in the Runfile's content
+  start: usize,
+}
+
+impl Line<'_> {
+  /// Span of `part`, a slice of the line's text
+  fn span(&self, part: &str) -> Span {
+    Span::in_line(self.number, self.start, self.text, part)
+  }
+  /// Point an error about this line at its text, unless it already points somewhere
+  fn locate(&self, error: anyhow::Error) -> Diagnostic {
+    match error.downcast::<Diagnostic>() {
+      Ok(diagnostic) => diagnostic,
+      Err(error) => Diagnostic::error(self.span(self.text.trim()), error.to_string()),
+    }
+  }
 }
 
 #[derive(Default)]
-pub struct TokenizePhase;
+pub struct TokenizePhase {
+  /// Tell script lines from arguments and flags by what they look like, warning about lines the grammar
+  /// would read differently, even if the Runfile doesn't ask for this with `@set legacy-grammar`
+  pub legacy_grammar: bool,
+}
 
 impl TokenizePhase {
   pub fn new() -> Self {
-    Self
+    Self::default()
+  }
+  pub fn with_legacy_grammar(legacy_grammar: bool) -> Self {
+    Self { legacy_grammar }
   }
   /// Check if a line is a group header separator (starts with # followed by one or more dashes)
   fn is_separator_line(&self, line: &str) -> bool {
     let trimmed = line.trim();
     trimmed.starts_with("# ") && trimmed.len() > 2 && trimmed[2..].chars().all(|c| c == '-')
   }
-  /// Check if a line is a command line (either ends with colon or is a simple command name)
+  /// Check if a line is a command attribute (an unindented line starting with @)
+  fn is_attribute_line(&self, line: &str) -> bool {
+    line.starts_with('@')
+  }
+  /// Check if a line starts a command: any unindented line that isn't a comment or an attribute
   fn is_command_line(&self, line: &str) -> bool {
+    if self.legacy_grammar {
+      return self.is_legacy_command_line(line);
+    }
     let trimmed = line.trim();
-    // Must not be a comment, echo, or shebang
-    if trimmed.starts_with('#') || trimmed.starts_with("echo") || trimmed.starts_with("#!/") {
+    !line.starts_with([' ', '\t']) && !trimmed.is_empty() && !trimmed.starts_with(['#', '@'])
+  }
+  /// Legacy grammar: check if a line is a command line (either ends with colon or is a simple command name)
+  fn is_legacy_command_line(&self, line: &str) -> bool {
+    let trimmed = line.trim();
+    // Must not be a comment, echo, shebang, or attribute
+    if trimmed.starts_with('#') || trimmed.starts_with("echo") || trimmed.starts_with("#!/") || trimmed.starts_with('@')
+    {
       return false;
     }
     // Must not be indented (arguments and flags are indented)
@@ -86,7 +173,7 @@ impl TokenizePhase {
     while i < parts.len() {
       let part = parts[i];
       // Stop at flags or special args
-      if part.starts_with('-') || part.contains('?') || part.contains("...") || part.contains('=') {
+      if part.starts_with('-') || part.contains(['?', '=', '<']) || part.contains("...") {
         break;
       }
       // If this part contains or ends with a comma, it's part of aliases
@@ -117,10 +204,149 @@ impl TokenizePhase {
     }
     false
   }
-  pub fn tokenize(&self, content: &str) -> Result<Vec<Token>> {
+  /// Tokenize the Runfile, failing with every error found in it
+  pub fn tokenize(&self, content: &str) -> Result<Vec<Spanned<Token>>> {
+    let (tokens, diagnostics) = self.tokenize_with_diagnostics(content);
+    diagnostics.into_result()?;
+    Ok(tokens)
+  }
+  /// Tokenize the Runfile, reporting problems instead of stopping at them
+  ///
+  /// A line that can't be read is left out, except that a command with a comment on its own line is still
+  /// read without the comment.
+  pub fn tokenize_with_diagnostics(&self, content: &str) -> (Vec<Spanned<Token>>, Diagnostics) {
+    // The setting changes how the lines above it are read too
+    if !self.legacy_grammar && sets_legacy_grammar(content) {
+      return Self::with_legacy_grammar(true).tokenize_with_diagnostics(content);
+    }
+    let (tokens, mut diagnostics) = self.tokenize_lines(content);
+    let warnings = self.grammar_changes(content, &tokens, &diagnostics);
+    diagnostics.extend(warnings);
+    diagnostics.sort();
+    (tokens, diagnostics)
+  }
+  /// The grammar to read `content` with: this one, or the legacy grammar if the Runfile sets it
+  pub(super) fn grammar_for(&self, content: &str) -> Self {
+    Self::with_legacy_grammar(self.legacy_grammar || sets_legacy_grammar(content))
+  }
+  /// The kind of each line read into a token, by line number, for the syntax tree
+  ///
+  /// Lines that are skipped, like descriptions and comments among parameters, or that can't be read aren't
+  /// included.
+  pub(super) fn line_kinds(&self, content: &str) -> HashMap<usize, LineKind> {
+    let (tokens, _) = self.tokenize_lines(content);
+    tokens
+      .iter()
+      .map(|token| {
+        let kind = match token.node {
+          Token::GroupHeader { .. } => LineKind::GroupName,
+          Token::CommandName { .. } => LineKind::Command,
+          Token::Argument { .. } | Token::Flag { .. } => LineKind::Parameter,
+          Token::ScriptLine { .. } => LineKind::Script,
+          Token::Comment { .. } => LineKind::Comment,
+          Token::Attribute { .. } => LineKind::Attribute,
+        };
+        (token.span.line, kind)
+      })
+      .collect()
+  }
+  /// Warn about each line that the grammar and the legacy grammar read differently
+  ///
+  /// Lines that the other grammar couldn't read had no meaning to keep, and errors in the grammar are
+  /// reported anyway, so both are left alone.
+  fn grammar_changes(&self, content: &str, tokens: &[Spanned<Token>], diagnostics: &Diagnostics) -> Diagnostics {
+    let current = readings(tokens, diagnostics);
+    let (other_tokens, other_diagnostics) = Self::with_legacy_grammar(!self.legacy_grammar).tokenize_lines(content);
+    let other = readings(&other_tokens, &other_diagnostics);
+    // Commands the legacy grammar leaves without a script were errors in older versions, not a meaning to keep
+    let scriptless = if self.legacy_grammar {
+      BTreeSet::new()
+    } else {
+      scriptless_parameters(&other_tokens)
+    };
+    // Lines after an error in the same command are read as the error left them, so they're left to it
+    let errors: BTreeSet<usize> = diagnostics
+      .iter()
+      .filter(|diagnostic| diagnostic.severity == Severity::Error)
+      .map(|diagnostic| diagnostic.span.line)
+      .collect();
+    let starts: BTreeSet<usize> = tokens
+      .iter()
+      .filter(|token| {
+        matches!(
+          token.node,
+          Token::CommandName { .. } | Token::GroupHeader { .. }
+        )
+      })
+      .map(|token| token.span.line)
+      .collect();
+    let after_error = |line: usize| {
+      errors
+        .range(..line)
+        .next_back()
+        .is_some_and(|&error| starts.range(error + 1..=line).next().is_none())
+    };
+    let lines: BTreeSet<usize> = current.keys().chain(other.keys()).copied().collect();
+    let mut warnings = Diagnostics::new();
+    for line in lines {
+      let (span, reading) = current.get(&line).cloned().unwrap_or_default();
+      let (other_span, other_reading) = other.get(&line).cloned().unwrap_or_default();
+      if reading == other_reading {
+        continue;
+      }
+      let span = if reading == Reading::Skipped {
+        other_span
+      } else {
+        span
+      };
+      let mut code = "grammar-change";
+      let message = if self.legacy_grammar {
+        // Errors in the Runfile as it's read are reported anyway
+        if matches!(reading, Reading::Error(_)) {
+          continue;
+        }
+        format!(
+          "This line {}, but without @set legacy-grammar it {}",
+          reading.describe("is"),
+          other_reading.describe("would be")
+        )
+      } else {
+        if matches!(reading, Reading::Error(_))
+          || after_error(line)
+          || matches!(other_reading, Reading::Error(_))
+          || scriptless.contains(&line)
+        {
+          continue;
+        }
+        if used_parameter(&other_tokens, line) {
+          code = "parameter-in-script";
+        }
+        format!(
+          "This line {}, but in older versions of run it {}. Add @set legacy-grammar to keep its old \
+           meaning",
+          reading.describe("is"),
+          other_reading.describe("was")
+        )
+      };
+      warnings.push(Diagnostic::warning(span, message).with_code(code));
+    }
+    warnings
+  }
+  /// Read the lines with the grammar or the legacy grammar, as set
+  fn tokenize_lines(&self, content: &str) -> (Vec<Spanned<Token>>, Diagnostics) {
     let mut tokens = Vec::new();
+    let mut diagnostics = Diagnostics::new();
     let lines: Vec<&str> = content.lines().collect();
+    // Lines are slices of the content, so their offsets locate them
+    let line_at = |index: usize| Line {
+      text: lines[index],
+      number: index + 1,
+      start: lines[index].as_ptr() as usize - content.as_ptr() as usize,
+    };
     let mut i = 0;
+    // Between a command line without a colon and the parameter line with one
+    let mut in_parameters = false;
+    // Legacy grammar: whether the command line had a colon, and a line after it was taken for script
     let mut in_script_body = false;
     let mut seen_script_line = false;
     while i < lines.len() {
@@ -131,18 +357,33 @@ impl TokenizePhase {
         let next_line = lines[i + 1].trim();
         let third_line = lines[i + 2].trim();
         if next_line.starts_with("# ") && self.is_separator_line(third_line) {
-          let group_name = next_line
-            .strip_prefix("# ")
-            .unwrap_or("")
-            .trim()
-            .to_string();
-          tokens.push(Token::GroupHeader { name: group_name });
+          let group_name = next_line.strip_prefix("# ").unwrap_or("").trim();
+          tokens.push(Spanned {
+            node: Token::GroupHeader {
+              name: group_name.to_string(),
+            },
+            span: line_at(i + 1).span(group_name),
+          });
+          in_parameters = false;
           in_script_body = false; // Reset script state for new group
           seen_script_line = false; // Reset script line tracking
           i += 3; // Skip the next two lines
           continue;
         }
       }
+      // Attributes apply to the command below them
+      if self.is_attribute_line(line) {
+        in_parameters = false;
+        match self.parse_attribute(trimmed) {
+          Ok(token) => tokens.push(Spanned {
+            node: token,
+            span: line_at(i).span(trimmed),
+          }),
+          Err(error) => diagnostics.push(line_at(i).locate(error)),
+        }
+        i += 1;
+        continue;
+      }
       // Check if this is a command line and look for comments above it
       // New syntax: colon must come after all flags and args, not directly after command
       if self.is_command_line(line) {
@@ -152,6 +393,14 @@ impl TokenizePhase {
         while j > 0 {
           j -= 1;
           let prev_line = lines[j].trim();
+          // Descriptions aren't indented, indented comments belong to the script above
+          if !self.legacy_grammar && lines[j].starts_with([' ', '\t']) {
+            break;
+          }
+          if self.is_attribute_line(lines[j]) {
+            // Attributes may sit between the description and the command
+            continue;
+          }
           if prev_line.starts_with('#') {
             // Skip group header separators and group names
             if !self.is_separator_line(prev_line) {
@@ -179,21 +428,45 @@ impl TokenizePhase {
         let comment = if comment_lines.is_empty() {
           None
         } else {
-          Some(comment_lines.join(" "))
+          Some(comment_lines.join("\n"))
+        };
+        let mut line = line_at(i);
+        // Comments belong on the line above; the command is still read without this one
+        if let Some(comment_start) = trimmed.find(" # ") {
+          diagnostics.push(Diagnostic::error(
+            line.span(&trimmed[comment_start + 1..]),
+            "Command comments must be on the line above the command, not on the same line",
+          ));
+          line.text = trimmed[..comment_start].trim_end();
+        }
+        let token = match self.parse_line_with_comment(&line, comment, in_script_body, &mut seen_script_line) {
+          Ok(token) => token,
+          Err(error) => {
+            diagnostics.push(line.locate(error));
+            None
+          }
         };
-        let token = self.parse_line_with_comment(line, comment, in_script_body, &mut seen_script_line)?;
         if let Some(token) = token {
           // Set script state based on whether command line ends with ':'
           if let Token::CommandName { .. } = &token {
+            in_parameters = !trimmed.ends_with(':');
             in_script_body = trimmed.ends_with(':');
             seen_script_line = false; // Reset script line tracking for new command
           }
-          tokens.push(token);
+          tokens.push(Spanned {
+            span: self.token_span(&line, &token),
+            node: token,
+          });
         }
       } else {
         // Check if this is a comment that might be attached to a command
         // Special case: shebang lines should be processed as script lines
-        if trimmed.starts_with('#') && !self.is_separator_line(trimmed) && !trimmed.starts_with("#!/") {
+        let indented = line.starts_with([' ', '\t']);
+        if trimmed.starts_with('#')
+          && !self.is_separator_line(trimmed)
+          && !trimmed.starts_with("#!/")
+          && (self.legacy_grammar || !indented)
+        {
           // Check if there's a command coming up (after any number of consecutive comments)
           let mut j = i + 1;
           let mut found_command = false;
@@ -201,10 +474,12 @@ impl TokenizePhase {
             let next_line = lines[j].trim();
             if next_line.is_empty() {
               j += 1;
-            } else if next_line.starts_with('#') && !self.is_separator_line(next_line) {
-              // Another comment, keep looking
+            } else if (next_line.starts_with('#') && !self.is_separator_line(next_line))
+              || self.is_attribute_line(lines[j])
+            {
+              // Another comment or an attribute, keep looking
               j += 1;
-            } else if self.is_command_line(next_line) {
+            } else if self.is_command_line(lines[j]) {
               // Found a command, skip this comment
               found_command = true;
               break;
@@ -220,35 +495,62 @@ impl TokenizePhase {
           }
         }
         // Process normally
-        let token = self.parse_line(line, in_script_body, &mut seen_script_line)?;
-        if let Some(token) = token {
-          tokens.push(token);
+        let line = line_at(i);
+        let token = if self.legacy_grammar {
+          self.parse_line(&line, in_script_body, &mut seen_script_line)
+        } else {
+          self.parse_body_line(&line, &mut in_parameters)
+        };
+        match token {
+          Ok(Some(token)) => tokens.push(Spanned {
+            span: self.token_span(&line, &token),
+            node: token,
+          }),
+          Ok(None) => {}
+          Err(error) => diagnostics.push(line.locate(error)),
         }
       }
       i += 1;
     }
-    Ok(tokens)
+    (tokens, diagnostics)
+  }
+  /// Span of a token parsed from `line`: its trimmed text, leaving out the comment and colon after an
+  /// argument or flag
+  fn token_span(&self, line: &Line, token: &Token) -> Span {
+    let trimmed = line.text.trim();
+    match token {
+      Token::Argument { .. } | Token::Flag { .. } => {
+        let content = match trimmed.find(" # ") {
+          Some(comment_start) => trimmed[..comment_start].trim_end(),
+          None => trimmed,
+        };
+        line.span(content.strip_suffix(':').unwrap_or(content))
+      }
+      _ => line.span(trimmed),
+    }
+  }
+  fn parse_attribute(&self, line: &str) -> Result<Token> {
+    let mut parts = line.strip_prefix('@').unwrap_or(line).split_whitespace();
+    let name = parts
+      .next()
+      .ok_or_else(|| anyhow::anyhow!("Attribute must have a name"))?
+      .to_string();
+    let args = parts.map(|part| part.to_string()).collect();
+    Ok(Token::Attribute { name, args })
   }
   fn parse_line_with_comment(
     &self,
-    line: &str,
+    line: &Line,
     comment: Option<String>,
     in_script_body: bool,
     seen_script_line: &mut bool,
   ) -> Result<Option<Token>> {
-    let trimmed = line.trim();
+    let trimmed = line.text.trim();
     if trimmed.is_empty() {
       return Ok(None);
     }
     // Command definition: command_name: (but not script lines)
-    if self.is_command_line(line) {
-      // Check for comments on the same line as command - these should not be allowed
-      if trimmed.contains(" # ") {
-        // This is an error - comments should be on the line above, not same line
-        return Err(anyhow::anyhow!(
-          "Command comments must be on the line above the command, not on the same line"
-        ));
-      }
+    if self.is_command_line(line.text) {
       // Parse command with potential inline args and flags
       let command_line = if trimmed.ends_with(':') {
         trimmed.strip_suffix(':').unwrap().trim()
@@ -260,7 +562,7 @@ impl TokenizePhase {
       let (inline_args, inline_flags) = if args_and_flags.is_empty() {
         (Vec::new(), Vec::new())
       } else {
-        self.parse_args_and_flags(args_and_flags)?
+        self.parse_args_and_flags(args_and_flags, line)?
       };
       return Ok(Some(Token::CommandName {
         name: aliases,
@@ -272,7 +574,7 @@ impl TokenizePhase {
     // Rest of the parsing logic for non-command lines
     self.parse_line(line, in_script_body, seen_script_line)
   }
-  fn parse_command_line(&self, line: &str) -> Result<(Vec<String>, Vec<String>)> {
+  pub(crate) fn parse_command_line<'a>(&self, line: &'a str) -> Result<(Vec<String>, Vec<&'a str>)> {
     let parts: Vec<&str> = line.split_whitespace().collect();
     let mut aliases = Vec::new();
     let mut args_and_flags = Vec::new();
@@ -282,7 +584,7 @@ impl TokenizePhase {
     while i < parts.len() {
       let part = parts[i];
       // Stop at flags or special args
-      if part.starts_with('-') || part.contains('?') || part.contains("...") || part.contains('=') {
+      if part.starts_with('-') || part.contains(['?', '=', '<']) || part.contains("...") {
         break;
       }
       // If this part contains or ends with a comma, it's part of aliases
@@ -309,7 +611,7 @@ impl TokenizePhase {
     }
     // Parse remaining args and flags
     while i < parts.len() {
-      args_and_flags.push(parts[i].to_string());
+      args_and_flags.push(parts[i]);
       i += 1;
     }
     if aliases.is_empty() {
@@ -317,12 +619,13 @@ impl TokenizePhase {
     }
     Ok((aliases, args_and_flags))
   }
-  fn parse_args_and_flags(&self, parts: Vec<String>) -> Result<ArgsAndFlagsResult> {
+  fn parse_args_and_flags(&self, parts: Vec<&str>, line: &Line) -> Result<ArgsAndFlagsResult> {
     let mut args = Vec::new();
     let mut flags = Vec::new();
     let mut i = 0;
     while i < parts.len() {
-      let part = &parts[i];
+      let part = parts[i];
+      let span = line.span(part);
       if part.starts_with("...") || part.ends_with("...") {
         // Varargs (support both prefix ...args and suffix args...)
         let arg_name = if part.starts_with("...") {
@@ -330,104 +633,235 @@ impl TokenizePhase {
         } else {
           part.strip_suffix("...").unwrap_or("args").to_string()
         };
-        args.push((arg_name, true, true));
+        args.push((arg_name, true, true, None, None, span));
         i += 1;
       } else if part.starts_with('-') {
         // This is a flag
         if part.ends_with(',') && i + 1 < parts.len() {
           // Comma-separated flag: -f, --flag
           let short_part = part.strip_suffix(',').unwrap();
-          let long_part = &parts[i + 1];
+          let long_part = parts[i + 1];
           let short = short_part.strip_prefix('-').and_then(|s| s.chars().next());
-          let (long_name, takes_value, type_hint) = self.parse_flag_name(long_part)?;
-          flags.push((long_name, short, takes_value, type_hint));
+          let (long_name, takes_value, type_hint, default) = self.parse_flag_name(long_part)?;
+          // The span covers both the short and the long flag
+          let span = Span {
+            end: line.span(long_part).end,
+            ..span
+          };
+          flags.push((long_name, short, takes_value, type_hint, default, span));
           i += 2; // Skip the next part since we processed it
         } else if part.starts_with("--") {
           // Long flag only: --flag or --flag=<type>
-          let (long_name, takes_value, type_hint) = self.parse_flag_name(part)?;
-          flags.push((long_name, None, takes_value, type_hint));
+          let (long_name, takes_value, type_hint, default) = self.parse_flag_name(part)?;
+          flags.push((long_name, None, takes_value, type_hint, default, span));
           i += 1;
         } else if part.len() == 2 && part.starts_with('-') {
           // Short flag only: -f
           let short = part.chars().nth(1).unwrap();
-          flags.push((format!("{}", short), Some(short), false, None));
+          flags.push((format!("{}", short), Some(short), false, None, None, span));
           i += 1;
         } else {
           i += 1;
         }
       } else {
         // This is an argument
-        let (arg_name, optional) = if part.ends_with('?') {
-          (part.strip_suffix('?').unwrap().to_string(), true)
-        } else {
-          (part.to_string(), false)
-        };
-        args.push((arg_name, optional, false));
+        let (arg_name, optional, type_hint, default) = self.parse_arg_name(part);
+        args.push((arg_name, optional, false, type_hint, default, span));
         i += 1;
       }
     }
     Ok((args, flags))
   }
-  fn parse_flag_name(&self, flag: &str) -> Result<(String, bool, Option<String>)> {
+  /// Parse an argument: `name`, optional `name?`, typed `name<hint>`, and `name=default` (which is also
+  /// optional)
+  fn parse_arg_name(&self, arg: &str) -> (String, bool, Option<String>, Option<String>) {
+    let (name, mut rest) = arg.split_at(arg.find(['<', '?', '=']).unwrap_or(arg.len()));
+    let mut type_hint = None;
+    if let Some((hint, after)) = rest.strip_prefix('<').and_then(|hint| hint.split_once('>')) {
+      type_hint = Some(hint.to_string());
+      rest = after;
+    }
+    match rest {
+      "" => (name.to_string(), false, type_hint, None),
+      "?" => (name.to_string(), true, type_hint, None),
+      _ => match rest.strip_prefix('=') {
+        Some(default) => (name.to_string(), true, type_hint, Some(default.to_string())),
+        // Not a recognized form, keep the whole word as the name
+        None => (arg.to_string(), false, None, None),
+      },
+    }
+  }
+  fn parse_flag_name(&self, flag: &str) -> Result<(String, bool, Option<String>, Option<String>)> {
     let flag = flag.strip_prefix("--").unwrap_or(flag);
-    if flag.contains('=') {
-      // Value flag: --output=<file>
-      let parts: Vec<&str> = flag.split('=').collect();
-      if parts.len() == 2 {
-        let name = parts[0].to_string();
-        let type_hint = parts[1]
-          .strip_prefix('<')
-          .and_then(|s| s.strip_suffix('>'))
-          .map(|s| s.to_string());
-        return Ok((name, true, type_hint));
-      }
+    if let Some((name, value)) = flag.split_once('=') {
+      // Value flag: --output=<file>, optionally with a default: --mode=<name>=fast
+      let (type_hint, default) = match value
+        .strip_prefix('<')
+        .and_then(|value| value.split_once('>'))
+      {
+        Some((type_hint, rest)) => (
+          Some(type_hint.to_string()),
+          rest.strip_prefix('=').map(|default| default.to_string()),
+        ),
+        None => (None, None),
+      };
+      return Ok((name.to_string(), true, type_hint, default));
     }
     // Boolean flag: --flag
-    Ok((flag.to_string(), false, None))
+    Ok((flag.to_string(), false, None, None))
   }
-  fn parse_line(&self, line: &str, in_script_body: bool, seen_script_line: &mut bool) -> Result<Option<Token>> {
-    let trimmed = line.trim();
+  /// Read a line below a command line: a parameter declaration while `in_parameters`, otherwise part of
+  /// the script when indented, or a comment
+  fn parse_body_line(&self, line: &Line, in_parameters: &mut bool) -> Result<Option<Token>> {
+    let trimmed = line.text.trim();
     if trimmed.is_empty() {
       return Ok(None);
     }
-    // Command definition: command_name: (but not script lines)
-    if self.is_command_line(line) {
-      // Check for comments on the same line as command - these should not be allowed
-      if trimmed.contains(" # ") {
-        // This is an error - comments should be on the line above, not same line
-        return Err(anyhow::anyhow!(
-          "Command comments must be on the line above the command, not on the same line"
-        ));
+    // Unindented lines here are comments, since everything else unindented starts a command
+    if !line.text.starts_with([' ', '\t']) {
+      if *in_parameters {
+        return Ok(None);
       }
-      // Parse command with potential inline args and flags
-      let command_line = if trimmed.ends_with(':') {
-        trimmed.strip_suffix(':').unwrap().trim()
-      } else {
-        trimmed
-      };
-      // Parse the command line: name[, alias]* [arg|flag]*
-      let (aliases, args_and_flags) = self.parse_command_line(command_line)?;
-      let (inline_args, inline_flags) = if args_and_flags.is_empty() {
-        (Vec::new(), Vec::new())
-      } else {
-        self.parse_args_and_flags(args_and_flags)?
-      };
-      return Ok(Some(Token::CommandName {
-        name: aliases,
-        inline_args,
-        inline_flags,
-        comment: None,
+      return Ok(Some(Token::Comment {
+        content: trimmed.to_string(),
+      }));
+    }
+    if !*in_parameters {
+      return Ok(Some(Token::ScriptLine {
+        content: line.text.to_string(),
       }));
     }
+    if trimmed.starts_with('#') {
+      return Ok(None);
+    }
+    let (declaration, comment) = match trimmed.split_once(" # ") {
+      Some((declaration, comment)) => (declaration.trim_end(), Some(comment.trim().to_string())),
+      None => (trimmed, None),
+    };
+    let ends_parameters = declaration.ends_with(':');
+    // One word, or two for `-s, --long`
+    let words: Vec<&str> = declaration.split_whitespace().collect();
+    let is_declaration = match words.as_slice() {
+      [_] => true,
+      [short, _] => short.starts_with('-') && short.ends_with(','),
+      _ => false,
+    };
+    let token = if is_declaration {
+      self.parse_parameter(declaration, comment)?
+    } else {
+      None
+    };
+    match token {
+      Some(token) => {
+        *in_parameters = !ends_parameters;
+        Ok(Some(token))
+      }
+      None => {
+        // Take the rest as script, as if the parameters had ended with a colon
+        *in_parameters = false;
+        Err(anyhow!(
+          "Expected an argument or flag; the last one ends with ':' and the script comes after it"
+        ))
+      }
+    }
+  }
+  /// Parse an argument or flag declaration, with its description already split off and an optional
+  /// trailing colon
+  fn parse_parameter(&self, content_part: &str, comment: Option<String>) -> Result<Option<Token>> {
+    // Check if it's a flag: -s, --long or --long
+    if content_part.starts_with('-') {
+      let parts: Vec<&str> = content_part.split(',').map(|s| s.trim()).collect();
+      if parts.len() == 2 {
+        // -s, --long format
+        let short = parts[0].strip_prefix('-').and_then(|s| s.chars().next());
+        let long_part = parts[1];
+        // Strip trailing colon if present
+        let clean_long_part = long_part.strip_suffix(':').unwrap_or(long_part);
+        let (long_name, takes_value, type_hint, default) = self.parse_flag_name(clean_long_part)?;
+        return Ok(Some(Token::Flag {
+          long_name,
+          short,
+          takes_value,
+          type_hint,
+          default,
+          comment,
+        }));
+      } else if content_part.starts_with("--") {
+        // --long format
+        // Strip trailing colon if present
+        let clean_content = content_part.strip_suffix(':').unwrap_or(content_part);
+        let (long_name, takes_value, type_hint, default) = self.parse_flag_name(clean_content)?;
+        return Ok(Some(Token::Flag {
+          long_name,
+          short: None,
+          takes_value,
+          type_hint,
+          default,
+          comment,
+        }));
+      }
+    } else {
+      // Check if it's an argument (no dashes, simple identifier)
+      // Arguments must be a single word (no spaces after removing comment)
+      if !content_part.contains(' ') && !content_part.is_empty() && !content_part.starts_with('-') {
+        // Strip trailing colon if present
+        let clean_content = content_part.strip_suffix(':').unwrap_or(content_part);
+        let (arg_name, optional, is_varargs, type_hint, default) = if clean_content.starts_with("...") {
+          (
+            clean_content
+              .strip_prefix("...")
+              .unwrap_or("args")
+              .to_string(),
+            true,
+            true,
+            None,
+            None,
+          )
+        } else if clean_content.ends_with("...") {
+          (
+            clean_content
+              .strip_suffix("...")
+              .unwrap_or("args")
+              .to_string(),
+            true,
+            true,
+            None,
+            None,
+          )
+        } else {
+          let (arg_name, optional, type_hint, default) = self.parse_arg_name(clean_content);
+          (arg_name, optional, false, type_hint, default)
+        };
+        return Ok(Some(Token::Argument {
+          name: arg_name,
+          optional,
+          is_varargs,
+          type_hint,
+          default,
+          comment,
+        }));
+      }
+    }
+    Ok(None)
+  }
+  fn parse_line(&self, line: &Line, in_script_body: bool, seen_script_line: &mut bool) -> Result<Option<Token>> {
+    let trimmed = line.text.trim();
+    if trimmed.is_empty() {
+      return Ok(None);
+    }
+    // Command definition: command_name: (but not script lines)
+    if self.is_command_line(line.text) {
+      return self.parse_line_with_comment(line, None, in_script_body, seen_script_line);
+    }
     // Indented argument or flag: must be exactly 2 spaces, no more, or tab-indented
     // Allow shebang lines even when indented
-    if ((line.starts_with("  ") && !line.starts_with("   ")) || line.starts_with("\t"))
+    if ((line.text.starts_with("  ") && !line.text.starts_with("   ")) || line.text.starts_with("\t"))
       && (!trimmed.starts_with('#') || trimmed.starts_with("#!/"))
     {
       // If we're in script body and have seen a script line, treat all indented lines as script
       if in_script_body && *seen_script_line {
         return Ok(Some(Token::ScriptLine {
-          content: line.to_string(),
+          content: line.text.to_string(),
         }));
       }
       // If we're in script body and this looks like a script line (not an argument/flag), treat it as script
@@ -449,7 +883,7 @@ impl TokenizePhase {
         {
           *seen_script_line = true;
           return Ok(Some(Token::ScriptLine {
-            content: line.to_string(),
+            content: line.text.to_string(),
           }));
         }
       }
@@ -469,79 +903,11 @@ impl TokenizePhase {
       // Check if it's a shebang line
       if content_part.starts_with("#!/") {
         return Ok(Some(Token::ScriptLine {
-          content: line.to_string(),
+          content: line.text.to_string(),
         }));
       }
-      // Check if it's a flag: -s, --long or --long
-      if content_part.starts_with('-') {
-        let parts: Vec<&str> = content_part.split(',').map(|s| s.trim()).collect();
-        if parts.len() == 2 {
-          // -s, --long format
-          let short = parts[0].strip_prefix('-').and_then(|s| s.chars().next());
-          let long_part = parts[1];
-          // Strip trailing colon if present
-          let clean_long_part = long_part.strip_suffix(':').unwrap_or(long_part);
-          let (long_name, takes_value, type_hint) = self.parse_flag_name(clean_long_part)?;
-          return Ok(Some(Token::Flag {
-            long_name,
-            short,
-            takes_value,
-            type_hint,
-            comment,
-          }));
-        } else if content_part.starts_with("--") {
-          // --long format
-          // Strip trailing colon if present
-          let clean_content = content_part.strip_suffix(':').unwrap_or(content_part);
-          let (long_name, takes_value, type_hint) = self.parse_flag_name(clean_content)?;
-          return Ok(Some(Token::Flag {
-            long_name,
-            short: None,
-            takes_value,
-            type_hint,
-            comment,
-          }));
-        }
-      } else {
-        // Check if it's an argument (no dashes, simple identifier)
-        // Arguments must be a single word (no spaces after removing comment)
-        if !content_part.contains(' ') && !content_part.is_empty() && !content_part.starts_with('-') {
-          // Strip trailing colon if present
-          let clean_content = content_part.strip_suffix(':').unwrap_or(content_part);
-          let (arg_name, optional, is_varargs) = if clean_content.starts_with("...") {
-            (
-              clean_content
-                .strip_prefix("...")
-                .unwrap_or("args")
-                .to_string(),
-              true,
-              true,
-            )
-          } else if clean_content.ends_with("...") {
-            (
-              clean_content
-                .strip_suffix("...")
-                .unwrap_or("args")
-                .to_string(),
-              true,
-              true,
-            )
-          } else if clean_content.ends_with('?') {
-            (
-              clean_content.strip_suffix('?').unwrap().to_string(),
-              true,
-              false,
-            )
-          } else {
-            (clean_content.to_string(), false, false)
-          };
-          return Ok(Some(Token::Argument {
-            name: arg_name,
-            optional,
-            is_varargs,
-            comment,
-          }));
-        }
+      if let Some(token) = self.parse_parameter(content_part, comment)? {
+        return Ok(Some(token));
       }
     }
     // Comment or script line
@@ -550,7 +916,7 @@ impl TokenizePhase {
       if trimmed.starts_with("#!/") {
         *seen_script_line = true;
         Ok(Some(Token::ScriptLine {
-          content: line.to_string(),
+          content: line.text.to_string(),
         }))
       } else {
         Ok(Some(Token::Comment {
@@ -560,16 +926,134 @@ impl TokenizePhase {
     } else {
       *seen_script_line = true;
       Ok(Some(Token::ScriptLine {
-        content: line.to_string(),
+        content: line.text.to_string(),
       }))
     }
   }
 }
 
+/// How a line of the Runfile is read, for comparing the grammars
+#[derive(Debug, Clone, Default, PartialEq)]
+enum Reading {
+  /// Not part of any token, like a blank line or a description
+  #[default]
+  Skipped,
+  /// Read as the kind of token described
+  Token(&'static str),
+  /// An error, with its message
+  Error(String),
+}
+
+impl Reading {
+  /// Describe how the line is read, with `verb` like "is" or "was"
+  fn describe(&self, verb: &str) -> String {
+    match self {
+      Reading::Skipped => format!("{} skipped", verb),
+      Reading::Token(kind) => format!("{} read as {}", verb, kind),
+      Reading::Error(message) => format!("{} an error: {}", verb, message),
+    }
+  }
+}
+
+/// Whether the token on `line` is a parameter its command's script surely meant to have: a flag, which no
+/// script line starts with, or an argument whose variable the script uses
+fn used_parameter(tokens: &[Spanned<Token>], line: usize) -> bool {
+  let Some(index) = tokens.iter().position(|token| token.span.line == line) else {
+    return false;
+  };
+  match &tokens[index].node {
+    Token::Flag { .. } => true,
+    Token::Argument { name, .. } => {
+      let variable = format!(r"\$\{{?(?i:{})\b", regex::escape(name));
+      let Ok(variable) = Regex::new(&variable) else {
+        return false;
+      };
+      tokens[index + 1..]
+        .iter()
+        .take_while(|token| {
+          !matches!(
+            token.node,
+            Token::CommandName { .. } | Token::GroupHeader { .. }
+          )
+        })
+        .any(|token| matches!(&token.node, Token::ScriptLine { content } if variable.is_match(content)))
+    }
+    _ => false,
+  }
+}
+
+/// Whether the Runfile turns the legacy grammar on with `@set legacy-grammar`
+fn sets_legacy_grammar(content: &str) -> bool {
+  content
+    .lines()
+    .any(|line| line.split_whitespace().eq(["@set", "legacy-grammar"]))
+}
+
+/// How each line with a token or an error is read, by line number
+fn readings(tokens: &[Spanned<Token>], diagnostics: &Diagnostics) -> BTreeMap<usize, (Span, Reading)> {
+  let mut readings = BTreeMap::new();
+  for token in tokens {
+    let kind = match token.node {
+      Token::GroupHeader { .. } => "a group header",
+      Token::CommandName { .. } => "a command",
+      Token::Argument { .. } => "an argument",
+      Token::Flag { .. } => "a flag",
+      Token::ScriptLine { .. } | Token::Comment { .. } => "part of the script",
+      Token::Attribute { .. } => "an attribute",
+    };
+    readings.insert(token.span.line, (token.span, Reading::Token(kind)));
+  }
+  for diagnostic in diagnostics.iter() {
+    if diagnostic.severity == Severity::Error {
+      let reading = Reading::Error(diagnostic.message.clone());
+      readings.insert(diagnostic.span.line, (diagnostic.span, reading));
+    }
+  }
+  readings
+}
+
+/// Lines of the parameters of commands with neither a script nor dependencies, which can't be run
+fn scriptless_parameters(tokens: &[Spanned<Token>]) -> BTreeSet<usize> {
+  let mut scriptless = BTreeSet::new();
+  // The current command's parameter lines, and whether it can be run
+  let mut parameters = Vec::new();
+  let mut runnable = true;
+  let mut depends = false;
+  for token in tokens {
+    match &token.node {
+      Token::Attribute { name, .. } if name == "depends" => depends = true,
+      Token::CommandName { .. } | Token::GroupHeader { .. } => {
+        let previous = std::mem::take(&mut parameters);
+        if !runnable {
+          scriptless.extend(previous);
+        }
+        runnable = matches!(token.node, Token::GroupHeader { .. }) || depends;
+        depends = false;
+      }
+      Token::Argument { .. } | Token::Flag { .. } => parameters.push(token.span.line),
+      Token::ScriptLine { .. } => runnable = true,
+      _ => {}
+    }
+  }
+  if !runnable {
+    scriptless.extend(parameters);
+  }
+  scriptless
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
 
+  fn span(line: usize, column: usize, start: usize, end: usize) -> Span {
+    Span {
+      line,
+      column,
+      start,
+      end,
+    }
+  }
+
   // Group Header Tests
 
   #[test]
@@ -638,7 +1122,7 @@ mod tests {
         name: vec!["hello".to_string()],
         inline_args: vec![],
         inline_flags: vec![],
-        comment: Some("First comment Second comment".to_string())
+        comment: Some("First comment\nSecond comment".to_string())
       }
     );
   }
@@ -723,7 +1207,14 @@ mod tests {
       tokens[0],
       Token::CommandName {
         name: vec!["b".to_string(), "build".to_string()],
-        inline_args: vec![("target".to_string(), false, false)],
+        inline_args: vec![(
+          "target".to_string(),
+          false,
+          false,
+          None,
+          None,
+          span(1, 10, 9, 15)
+        )],
         inline_flags: vec![],
         comment: None
       }
@@ -740,7 +1231,14 @@ mod tests {
       Token::CommandName {
         name: vec!["r".to_string(), "run".to_string()],
         inline_args: vec![],
-        inline_flags: vec![("debug".to_string(), None, false, None)],
+        inline_flags: vec![(
+          "debug".to_string(),
+          None,
+          false,
+          None,
+          None,
+          span(1, 8, 7, 14)
+        )],
         comment: None
       }
     );
@@ -750,7 +1248,7 @@ mod tests {
   #[test]
   fn test_required_argument() {
     let tokenizer = TokenizePhase::new();
-    let content = "command:\n  arg";
+    let content = "command\n  arg:";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
       tokens[1],
@@ -758,6 +1256,8 @@ mod tests {
         name: "arg".to_string(),
         optional: false,
         is_varargs: false,
+        type_hint: None,
+        default: None,
         comment: None
       }
     );
@@ -766,7 +1266,7 @@ mod tests {
   #[test]
   fn test_optional_argument() {
     let tokenizer = TokenizePhase::new();
-    let content = "command:\n  arg?";
+    let content = "command\n  arg?:";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
       tokens[1],
@@ -774,6 +1274,8 @@ mod tests {
         name: "arg".to_string(),
         optional: true,
         is_varargs: false,
+        type_hint: None,
+        default: None,
         comment: None
       }
     );
@@ -782,7 +1284,7 @@ mod tests {
   #[test]
   fn test_varargs() {
     let tokenizer = TokenizePhase::new();
-    let content = "command:\n  ...args";
+    let content = "command\n  ...args:";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
       tokens[1],
@@ -790,6 +1292,8 @@ mod tests {
         name: "args".to_string(),
         optional: true,
         is_varargs: true,
+        type_hint: None,
+        default: None,
         comment: None
       }
     );
@@ -798,7 +1302,7 @@ mod tests {
   #[test]
   fn test_argument_with_comment() {
     let tokenizer = TokenizePhase::new();
-    let content = "command:\n  arg # This is an argument";
+    let content = "command\n  arg: # This is an argument";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
       tokens[1],
@@ -806,6 +1310,8 @@ mod tests {
         name: "arg".to_string(),
         optional: false,
         is_varargs: false,
+        type_hint: None,
+        default: None,
         comment: Some("This is an argument".to_string())
       }
     );
@@ -815,7 +1321,7 @@ mod tests {
   #[test]
   fn test_long_flag() {
     let tokenizer = TokenizePhase::new();
-    let content = "command:\n  --flag";
+    let content = "command\n  --flag:";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
       tokens[1],
@@ -824,6 +1330,7 @@ mod tests {
         short: None,
         takes_value: false,
         type_hint: None,
+        default: None,
         comment: None
       }
     );
@@ -832,7 +1339,7 @@ mod tests {
   #[test]
   fn test_short_and_long_flag() {
     let tokenizer = TokenizePhase::new();
-    let content = "command:\n  -r, --release";
+    let content = "command\n  -r, --release:";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
       tokens[1],
@@ -841,6 +1348,7 @@ mod tests {
         short: Some('r'),
         takes_value: false,
         type_hint: None,
+        default: None,
         comment: None
       }
     );
@@ -849,7 +1357,7 @@ mod tests {
   #[test]
   fn test_value_flag() {
     let tokenizer = TokenizePhase::new();
-    let content = "command:\n  --output=<file>";
+    let content = "command\n  --output=<file>:";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
       tokens[1],
@@ -858,6 +1366,7 @@ mod tests {
         short: None,
         takes_value: true,
         type_hint: Some("file".to_string()),
+        default: None,
         comment: None
       }
     );
@@ -866,7 +1375,7 @@ mod tests {
   #[test]
   fn test_flag_with_comment() {
     let tokenizer = TokenizePhase::new();
-    let content = "command:\n  --debug # Enable debug mode";
+    let content = "command\n  --debug: # Enable debug mode";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
       tokens[1],
@@ -875,11 +1384,66 @@ mod tests {
         short: None,
         takes_value: false,
         type_hint: None,
+        default: None,
         comment: Some("Enable debug mode".to_string())
       }
     );
   }
 
+  #[test]
+  fn test_value_flag_with_default() {
+    let tokenizer = TokenizePhase::new();
+    let content = "command\n  -m, --mode=<name>=fast:";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    assert_eq!(
+      tokens[1],
+      Token::Flag {
+        long_name: "mode".to_string(),
+        short: Some('m'),
+        takes_value: true,
+        type_hint: Some("name".to_string()),
+        default: Some("fast".to_string()),
+        comment: None
+      }
+    );
+  }
+
+  #[test]
+  fn test_argument_with_default() {
+    let tokenizer = TokenizePhase::new();
+    let content = "command\n  name=World: # Who to greet";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    assert_eq!(
+      tokens[1],
+      Token::Argument {
+        name: "name".to_string(),
+        optional: true,
+        is_varargs: false,
+        type_hint: None,
+        default: Some("World".to_string()),
+        comment: Some("Who to greet".to_string())
+      }
+    );
+  }
+
+  #[test]
+  fn test_argument_with_choices() {
+    let tokenizer = TokenizePhase::new();
+    let content = "command\n  target<dev|prod>?:";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    assert_eq!(
+      tokens[1],
+      Token::Argument {
+        name: "target".to_string(),
+        optional: true,
+        is_varargs: false,
+        type_hint: Some("dev|prod".to_string()),
+        default: None,
+        comment: None
+      }
+    );
+  }
+
   // Inline Args and Flags Tests
   #[test]
   fn test_inline_args() {
@@ -891,8 +1455,22 @@ mod tests {
       Token::CommandName {
         name: vec!["command".to_string()],
         inline_args: vec![
-          ("arg1".to_string(), false, false),
-          ("arg2".to_string(), true, false)
+          (
+            "arg1".to_string(),
+            false,
+            false,
+            None,
+            None,
+            span(1, 9, 8, 12)
+          ),
+          (
+            "arg2".to_string(),
+            true,
+            false,
+            None,
+            None,
+            span(1, 14, 13, 18)
+          )
         ],
         inline_flags: vec![],
         comment: None
@@ -911,8 +1489,22 @@ mod tests {
         name: vec!["command".to_string()],
         inline_args: vec![],
         inline_flags: vec![
-          ("debug".to_string(), Some('d'), false, None),
-          ("output".to_string(), None, true, Some("file".to_string()))
+          (
+            "debug".to_string(),
+            Some('d'),
+            false,
+            None,
+            None,
+            span(1, 9, 8, 19)
+          ),
+          (
+            "output".to_string(),
+            None,
+            true,
+            Some("file".to_string()),
+            None,
+            span(1, 21, 20, 35)
+          )
         ],
         comment: None
       }
@@ -928,13 +1520,50 @@ mod tests {
       tokens[0],
       Token::CommandName {
         name: vec!["command".to_string()],
-        inline_args: vec![("args".to_string(), true, true)],
+        inline_args: vec![(
+          "args".to_string(),
+          true,
+          true,
+          None,
+          None,
+          span(1, 9, 8, 15)
+        )],
         inline_flags: vec![],
         comment: None
       }
     );
   }
 
+  #[test]
+  fn test_inline_defaults() {
+    let tokenizer = TokenizePhase::new();
+    let content = "greet name=World --mode=<name>=fast:";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    assert_eq!(
+      tokens[0],
+      Token::CommandName {
+        name: vec!["greet".to_string()],
+        inline_args: vec![(
+          "name".to_string(),
+          true,
+          false,
+          None,
+          Some("World".to_string()),
+          span(1, 7, 6, 16)
+        )],
+        inline_flags: vec![(
+          "mode".to_string(),
+          None,
+          true,
+          Some("name".to_string()),
+          Some("fast".to_string()),
+          span(1, 18, 17, 35)
+        )],
+        comment: None
+      }
+    );
+  }
+
   // Script Line Tests
   #[test]
   fn test_script_lines() {
@@ -1018,22 +1647,188 @@ mod tests {
   #[test]
   fn test_script_lines_with_shebang() {
     let tokenizer = TokenizePhase::new();
-    let content = "#!/bin/bash\necho \"Hello\"";
+    let content = "hello:\n  #!/bin/bash\n  echo \"Hello\"";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
-      tokens[0],
+      tokens[1],
       Token::ScriptLine {
-        content: "#!/bin/bash".to_string()
+        content: "  #!/bin/bash".to_string()
       }
     );
     assert_eq!(
-      tokens[1],
+      tokens[2],
+      Token::ScriptLine {
+        content: "  echo \"Hello\"".to_string()
+      }
+    );
+  }
+
+  #[test]
+  fn test_everything_after_colon_is_script() {
+    let tokenizer = TokenizePhase::new();
+    let content = "build:\n  make\n  --verbose\n  # not a description\necho done";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    for (token, line) in tokens[1..4]
+      .iter()
+      .zip(["  make", "  --verbose", "  # not a description"])
+    {
+      assert_eq!(
+        *token,
+        Token::ScriptLine {
+          content: line.to_string()
+        }
+      );
+    }
+    // An unindented line always starts a command
+    assert_eq!(
+      tokens[4],
+      Token::CommandName {
+        name: vec!["echo".to_string()],
+        inline_args: vec![(
+          "done".to_string(),
+          false,
+          false,
+          None,
+          None,
+          span(5, 6, 53, 57)
+        )],
+        inline_flags: vec![],
+        comment: None
+      }
+    );
+  }
+
+  #[test]
+  fn test_parameters_end_at_colon() {
+    let tokenizer = TokenizePhase::new();
+    let content = "build\n  target\n  # The profile\n  --release: # Optimize\n  make";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    assert_eq!(tokens.len(), 4);
+    assert!(matches!(tokens[1].node, Token::Argument { .. }));
+    assert!(matches!(tokens[2].node, Token::Flag { .. }));
+    assert_eq!(
+      tokens[3],
+      Token::ScriptLine {
+        content: "  make".to_string()
+      }
+    );
+  }
+
+  #[test]
+  fn test_script_line_among_parameters_error() {
+    let tokenizer = TokenizePhase::new();
+    let content = "build\n  --release\n  cargo build\n  cargo test";
+    let (tokens, diagnostics) = tokenizer.tokenize_with_diagnostics(content);
+    // The error is all there is, without a warning about how older versions read it or the lines after it
+    assert_eq!(diagnostics.len(), 1);
+    let diagnostic = diagnostics.iter().next().unwrap();
+    assert_eq!(diagnostic.severity, Severity::Error);
+    assert_eq!(diagnostic.span.line, 3);
+    assert!(
+      diagnostic
+        .message
+        .starts_with("Expected an argument or flag")
+    );
+    // The lines after it are read as the script
+    assert_eq!(
+      tokens[2],
       Token::ScriptLine {
-        content: "echo \"Hello\"".to_string()
+        content: "  cargo test".to_string()
       }
     );
   }
 
+  #[test]
+  fn test_legacy_grammar_warns_about_changes() {
+    let content = "@set legacy-grammar\n\nbuild:\n  --release\n  cargo build $release\n\ntest:\n  ./test.sh";
+    let (tokens, diagnostics) = TokenizePhase::new().tokenize_with_diagnostics(content);
+    // The Runfile keeps its old meaning
+    assert!(matches!(tokens[2].node, Token::Flag { .. }));
+    let warnings: Vec<(Severity, usize, &str)> = diagnostics
+      .iter()
+      .map(|diagnostic| {
+        (
+          diagnostic.severity,
+          diagnostic.span.line,
+          diagnostic.message.as_str(),
+        )
+      })
+      .collect();
+    assert_eq!(
+      warnings,
+      vec![(
+        Severity::Warning,
+        4,
+        "This line is read as a flag, but without @set legacy-grammar it would be read as part of the script"
+      )]
+    );
+    // Without the setting, the option turns the legacy grammar on
+    let content = content.strip_prefix("@set legacy-grammar\n").unwrap();
+    let (_, diagnostics) = TokenizePhase::with_legacy_grammar(true).tokenize_with_diagnostics(content);
+    assert_eq!(diagnostics.len(), 1);
+    assert!(!diagnostics.has_errors());
+  }
+
+  #[test]
+  fn test_grammar_leaves_errors_alone() {
+    let content = "build\n  not a parameter\n  --y:\n  echo\n\ntest:\n  --verbose\n  cargo test\n";
+    let (_, diagnostics) = TokenizePhase::new().tokenize_with_diagnostics(content);
+    let found: Vec<(Severity, usize)> = diagnostics
+      .iter()
+      .map(|diagnostic| (diagnostic.severity, diagnostic.span.line))
+      .collect();
+    // The next command is read on its own again
+    assert_eq!(found, vec![(Severity::Error, 2), (Severity::Warning, 7)]);
+  }
+
+  #[test]
+  fn test_grammar_warns_about_old_meaning() {
+    let content = "build:\n  -r, --release   # Release\n  cargo build $release\n";
+    let (tokens, diagnostics) = TokenizePhase::new().tokenize_with_diagnostics(content);
+    assert!(matches!(tokens[1].node, Token::ScriptLine { .. }));
+    let warnings: Vec<(Severity, usize, &str)> = diagnostics
+      .iter()
+      .map(|diagnostic| {
+        (
+          diagnostic.severity,
+          diagnostic.span.line,
+          diagnostic.message.as_str(),
+        )
+      })
+      .collect();
+    assert_eq!(
+      warnings,
+      vec![(
+        Severity::Warning,
+        2,
+        "This line is read as part of the script, but in older versions of run it was read as a flag. Add \
+         @set legacy-grammar to keep its old meaning"
+      )]
+    );
+    // The flag's line can't be a shell command that was meant, so running the command fails
+    assert_eq!(
+      diagnostics.iter().next().unwrap().code,
+      Some("parameter-in-script")
+    );
+    // Older versions couldn't run a command whose only lines were parameters
+    let content = "build:\n  true\n\n@depends build\ntest:\n  true\n";
+    let (_, diagnostics) = TokenizePhase::new().tokenize_with_diagnostics(content);
+    let lines: Vec<usize> = diagnostics
+      .iter()
+      .map(|diagnostic| diagnostic.span.line)
+      .collect();
+    assert_eq!(lines, vec![6]);
+    // The script doesn't use the old argument, so running the line is what was meant
+    assert_eq!(
+      diagnostics.iter().next().unwrap().code,
+      Some("grammar-change")
+    );
+    // Lines written for the current grammar mean the same to both
+    let content = "build\n  -r, --release:  # Release\n  cargo build $release\n";
+    let (_, diagnostics) = TokenizePhase::new().tokenize_with_diagnostics(content);
+    assert!(diagnostics.is_empty());
+  }
+
   // Comment Tests
   #[test]
   fn test_standalone_comments() {
@@ -1054,6 +1849,64 @@ mod tests {
     );
   }
 
+  // Attribute Tests
+  #[test]
+  fn test_attribute_above_command() {
+    let tokenizer = TokenizePhase::new();
+    let content = "# Run tests\n@passthrough\ntest ...args:";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    assert_eq!(
+      tokens[0],
+      Token::Attribute {
+        name: "passthrough".to_string(),
+        args: vec![]
+      }
+    );
+    assert_eq!(
+      tokens[1],
+      Token::CommandName {
+        name: vec!["test".to_string()],
+        inline_args: vec![(
+          "args".to_string(),
+          true,
+          true,
+          None,
+          None,
+          span(3, 6, 30, 37)
+        )],
+        inline_flags: vec![],
+        comment: Some("Run tests".to_string())
+      }
+    );
+  }
+
+  #[test]
+  fn test_attribute_with_args() {
+    let tokenizer = TokenizePhase::new();
+    let content = "@example one two\nhello:";
+    let tokens = tokenizer.tokenize(content).unwrap();
+    assert_eq!(
+      tokens[0],
+      Token::Attribute {
+        name: "example".to_string(),
+        args: vec!["one".to_string(), "two".to_string()]
+      }
+    );
+  }
+
+  #[test]
+  fn test_empty_attribute_error() {
+    let tokenizer = TokenizePhase::new();
+    let result = tokenizer.tokenize("@\nhello:");
+    assert!(result.is_err());
+    assert!(
+      result
+        .unwrap_err()
+        .to_string()
+        .contains("Attribute must have a name")
+    );
+  }
+
   // Error Tests
   #[test]
   fn test_command_with_inline_comment_error() {
@@ -1083,6 +1936,40 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_tokenize_continues_past_errors() {
+    let tokenizer = TokenizePhase::new();
+    let content = "@\nbuild # Build it:\n  cargo build\n\n:\n\ntest:\n  cargo test";
+    let (tokens, diagnostics) = tokenizer.tokenize_with_diagnostics(content);
+    let problems: Vec<(usize, &str)> = diagnostics
+      .iter()
+      .map(|diagnostic| (diagnostic.span.line, diagnostic.message.as_str()))
+      .collect();
+    assert_eq!(
+      problems,
+      vec![
+        (1, "Attribute must have a name"),
+        (
+          2,
+          "Command comments must be on the line above the command, not on the same line"
+        ),
+        (5, "Command must have at least one name"),
+      ]
+    );
+    // The command with a comment on its line is still read, without the comment
+    let names: Vec<&Vec<String>> = tokens
+      .iter()
+      .filter_map(|token| match &token.node {
+        Token::CommandName { name, .. } => Some(name),
+        _ => None,
+      })
+      .collect();
+    assert_eq!(
+      names,
+      vec![&vec!["build".to_string()], &vec!["test".to_string()]]
+    );
+  }
+
   // Edge Cases
   #[test]
   fn test_unicode_command() {
@@ -1103,7 +1990,7 @@ mod tests {
   #[test]
   fn test_unicode_arguments() {
     let tokenizer = TokenizePhase::new();
-    let content = "command:\n  🚀? # Rocket argument";
+    let content = "command\n  🚀?: # Rocket argument";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
       tokens[1],
@@ -1111,6 +1998,8 @@ mod tests {
         name: "🚀".to_string(),
         optional: true,
         is_varargs: false,
+        type_hint: None,
+        default: None,
         comment: Some("Rocket argument".to_string())
       }
     );
@@ -1135,7 +2024,7 @@ mod tests {
   #[test]
   fn test_empty_lines() {
     let tokenizer = TokenizePhase::new();
-    let content = "\n\ncommand:\n\n  arg\n\n";
+    let content = "\n\ncommand\n\n  arg:\n\n";
     let tokens = tokenizer.tokenize(content).unwrap();
     assert_eq!(
       tokens[0],
@@ -1152,6 +2041,8 @@ mod tests {
         name: "arg".to_string(),
         optional: false,
         is_varargs: false,
+        type_hint: None,
+        default: None,
         comment: None
       }
     );
@@ -1161,7 +2052,7 @@ mod tests {
   #[test]
   fn test_complete_runfile() {
     let tokenizer = TokenizePhase::new();
-    let content = "# ----------\n# Basic Commands\n# ----------\n\n# Simple command\nhello:\n  echo \"Hello, World!\"\n\n# Command with args\nbuild:\n  --debug     # Enable debug mode\n  --release   # Build in release mode\n  echo \"Building\"";
+    let content = "# ----------\n# Basic Commands\n# ----------\n\n# Simple command\nhello:\n  echo \"Hello, World!\"\n\n# Command with args\nbuild\n  --debug     # Enable debug mode\n  --release:  # Build in release mode\n  echo \"Building\"";
     let tokens = tokenizer.tokenize(content).unwrap();
 
     assert_eq!(
@@ -1201,6 +2092,7 @@ mod tests {
         short: None,
         takes_value: false,
         type_hint: None,
+        default: None,
         comment: Some("Enable debug mode".to_string())
       }
     );
@@ -1211,6 +2103,7 @@ mod tests {
         short: None,
         takes_value: false,
         type_hint: None,
+        default: None,
         comment: Some("Build in release mode".to_string())
       }
     );
@@ -1,12 +1,42 @@
-use std::{fs, path::PathBuf, process::Output};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  process::Output,
+  time::Duration,
+};
 
 use anyhow::Result;
 
-use crate::phases::{ParsePhase, ResolvePhase, RunPhase, TokenizePhase, run::OutputMode};
+use crate::{
+  format, lint,
+  phases::{
+    ParsePhase, ResolvePhase, RunPhase, TokenizePhase,
+    diagnostic::{Diagnostic, Diagnostics, Report, Severity},
+    parse::Runfile,
+    resolve::ExecutionPlan,
+    run::{DEFAULT_GRACE_PERIOD, OutputMode, ParallelOutput, Parallelism},
+    syntax::SyntaxTree,
+  },
+};
 
 #[derive(Default)]
 pub struct PipelineOptions {
+  /// Directory to start searching for a Runfile from (defaults to the current directory)
   pub directory: Option<PathBuf>,
+  /// Explicit Runfile path, bypassing discovery
+  pub runfile: Option<PathBuf>,
+  /// Time a script gets to exit after a forwarded signal before it is killed
+  pub grace_period: Option<Duration>,
+  /// Maximum number of commands to run at once; more than one runs dependencies in parallel
+  pub jobs: Option<usize>,
+  /// Keep running commands that don't depend on a failed one, instead of stopping everything
+  pub keep_going: bool,
+  /// Find a command from an unambiguous prefix of its name, as `@set prefix-matching` does
+  pub prefix_matching: bool,
+  /// Read the Runfile with the legacy grammar, as `@set legacy-grammar` does
+  pub legacy_grammar: bool,
+  /// Print the Runfile's warnings to stderr when it's read for a command, the listing or formatting
+  pub print_warnings: bool,
 }
 
 pub struct Pipeline {
@@ -29,14 +59,22 @@ impl Pipeline {
   }
   pub fn with_options(options: PipelineOptions) -> Self {
     Self {
-      tokenize: TokenizePhase::new(),
+      tokenize: TokenizePhase::with_legacy_grammar(options.legacy_grammar),
       parse: ParsePhase::new(),
-      resolve: ResolvePhase::new(),
-      run: RunPhase::new(),
+      resolve: ResolvePhase::with_prefix_matching(options.prefix_matching),
+      run: RunPhase::with_grace_period(options.grace_period.unwrap_or(DEFAULT_GRACE_PERIOD)),
       options,
     }
   }
   pub fn find_runfile(&self) -> Result<PathBuf> {
+    if let Some(runfile) = &self.options.runfile {
+      // Make the path absolute so the Runfile's parent is always a usable working directory
+      let runfile_path = std::env::current_dir()?.join(runfile);
+      if !runfile_path.is_file() {
+        return Err(anyhow::anyhow!("Runfile not found: {}", runfile.display()));
+      }
+      return Ok(runfile_path);
+    }
     let mut current_dir = if let Some(dir) = &self.options.directory {
       dir.clone()
     } else {
@@ -58,54 +96,328 @@ impl Pipeline {
     ))
   }
   pub fn execute_command_inherit(&self, command_name: &str, cli_args: Vec<String>) -> Result<()> {
-    // Phase 1: Find and read Runfile
-    let runfile_path = self.find_runfile()?;
-    let runfile_dir = runfile_path
-      .parent()
-      .ok_or_else(|| anyhow::anyhow!("Runfile path has no parent directory"))?;
-    let content = fs::read_to_string(&runfile_path)?;
-    // Phase 2: Tokenize
-    let tokens = self.tokenize.tokenize(&content)?;
-    // Phase 3: Parse
-    let runfile = self.parse.parse(tokens)?;
-    // Phase 4: Resolve
-    let command = self.resolve.resolve(runfile, command_name)?;
-    // Phase 5: Run with inherit mode
-    self
-      .run
-      .run_in_directory(command, cli_args, OutputMode::Inherit, Some(runfile_dir))?;
+    let (source, runfile, warnings) = self.load_quietly()?;
+    let runfile_dir = source.dir();
+    // `run help <command>` shows a command's help, unless the Runfile has a `help` command of its own
+    if command_name == "help" && runfile.command("help").is_none() {
+      self.print_warnings(&source, warnings);
+      match cli_args.first() {
+        Some(name) => {
+          let command = self
+            .resolve
+            .resolve(runfile, name)
+            .map_err(|error| source.report(error))?;
+          print!("{}", command.generate_help(true));
+        }
+        None => runfile.generate_help_output(true, false),
+      }
+      return Ok(());
+    }
+    let starts = command_starts(&runfile);
+    // Phase 4: Resolve the command and its dependencies
+    let plan = self
+      .resolve
+      .resolve_plan(runfile, command_name)
+      .map_err(|error| source.report(error))?;
+    self.check_grammar_changes(&source, &plan, &starts, warnings)?;
+    if plan.target().wants_help(&cli_args) {
+      print!("{}", plan.target().generate_help(true));
+      return Ok(());
+    }
+    let jobs = self.options.jobs.unwrap_or(1);
+    if jobs > 1 {
+      self
+        .run
+        .validate_args(plan.target(), cli_args.clone(), Some(runfile_dir))?;
+      let parallelism = Parallelism {
+        jobs,
+        keep_going: self.options.keep_going,
+      };
+      return self.run.run_parallel(
+        plan,
+        cli_args,
+        parallelism,
+        Some(runfile_dir),
+        &ParallelOutput::stdio(),
+      );
+    }
+    self.execute(runfile_dir, plan, cli_args, OutputMode::Inherit)?;
     Ok(())
   }
   pub fn execute_command(&self, command_name: &str, cli_args: Vec<String>) -> Result<Output> {
-    // Phase 1: Find and read Runfile
-    let runfile_path = self.find_runfile()?;
-    let runfile_dir = runfile_path
-      .parent()
-      .ok_or_else(|| anyhow::anyhow!("Runfile path has no parent directory"))?;
-    let content = fs::read_to_string(&runfile_path)?;
+    let (source, runfile, warnings) = self.load_quietly()?;
+    let starts = command_starts(&runfile);
+    // Phase 4: Resolve the command and its dependencies
+    let plan = self
+      .resolve
+      .resolve_plan(runfile, command_name)
+      .map_err(|error| source.report(error))?;
+    self.check_grammar_changes(&source, &plan, &starts, warnings)?;
+    let output = self.execute(source.dir(), plan, cli_args, OutputMode::Capture)?;
+    output.ok_or_else(|| anyhow::anyhow!("Expected output from capture mode"))
+  }
+  /// Tokenize, parse and check a Runfile as a whole, carrying on past problems to find them all
+  ///
+  /// The diagnostics are in the order of the lines they point at. Lines with errors are left out of the
+  /// Runfile.
+  pub fn parse_with_diagnostics(&self, content: &str) -> (Runfile, Diagnostics) {
     // Phase 2: Tokenize
-    let tokens = self.tokenize.tokenize(&content)?;
+    let (tokens, mut diagnostics) = self.tokenize.tokenize_with_diagnostics(content);
     // Phase 3: Parse
-    let runfile = self.parse.parse(tokens)?;
-    // Phase 4: Resolve
-    let command = self.resolve.resolve(runfile, command_name)?;
-    // Phase 5: Run with capture mode
+    let (runfile, parse_diagnostics) = self.parse.parse_with_diagnostics(tokens);
+    diagnostics.extend(parse_diagnostics);
+    diagnostics.extend(self.resolve.validate_runfile(&runfile));
+    diagnostics.sort();
+    (runfile, diagnostics)
+  }
+  /// Like [`Self::parse_with_diagnostics`], but also checks every command and its dependencies, not just
+  /// those a run would use
+  pub fn validate_with_diagnostics(&self, content: &str) -> (Runfile, Diagnostics) {
+    let (runfile, mut diagnostics) = self.parse_with_diagnostics(content);
+    diagnostics.extend(self.resolve.validate_commands(&runfile));
+    diagnostics.sort();
+    (runfile, diagnostics)
+  }
+  /// Find, read and parse the Runfile, failing with all of its errors, and returning its warnings after
+  /// printing them if the options ask for that
+  fn load(&self) -> Result<(Source, Runfile, Diagnostics)> {
+    let (source, runfile, warnings) = self.load_quietly()?;
+    self.print_warnings(&source, warnings.clone());
+    Ok((source, runfile, warnings))
+  }
+  /// Like [`Self::load`], but leaves printing the warnings to the caller
+  fn load_quietly(&self) -> Result<(Source, Runfile, Diagnostics)> {
+    let source = self.read()?;
+    let (runfile, diagnostics) = self.parse_with_diagnostics(&source.content);
+    if diagnostics.has_errors() {
+      return Err(source.report_all(diagnostics).into());
+    }
+    Ok((source, runfile, diagnostics))
+  }
+  fn print_warnings(&self, source: &Source, warnings: Diagnostics) {
+    if self.options.print_warnings && !warnings.is_empty() {
+      eprintln!("{}", source.report_all(warnings));
+    }
+  }
+  /// Print the warnings, failing instead on lines of the plan's commands that older versions of run read as
+  /// parameters the script uses, since those commands won't do what they were written to do
+  fn check_grammar_changes(
+    &self,
+    source: &Source,
+    plan: &ExecutionPlan,
+    starts: &[usize],
+    warnings: Diagnostics,
+  ) -> Result<()> {
+    let mut errors = Diagnostics::new();
+    let mut rest = Diagnostics::new();
+    for diagnostic in warnings {
+      // Lines belong to the last command that starts before them
+      let command = starts
+        .iter()
+        .rev()
+        .find(|&&start| start <= diagnostic.span.line);
+      let planned = command.is_some_and(|&start| {
+        plan
+          .steps
+          .iter()
+          .any(|step| step.command.span.line == start)
+      });
+      if diagnostic.code == Some("parameter-in-script") && planned {
+        errors.push(Diagnostic {
+          severity: Severity::Error,
+          ..diagnostic
+        });
+      } else {
+        rest.push(diagnostic);
+      }
+    }
+    self.print_warnings(source, rest);
+    errors
+      .into_result()
+      .map_err(|errors| source.report_all(errors).into())
+  }
+  /// Find and read the Runfile
+  fn read(&self) -> Result<Source> {
+    // Phase 1: Find and read Runfile
+    let path = self.find_runfile()?;
+    let content = fs::read_to_string(&path)?;
+    Ok(Source { path, content })
+  }
+  /// Run a command after its dependencies
+  ///
+  /// In capture mode, the output of the dependencies comes before the command's own output.
+  fn execute(
+    &self,
+    runfile_dir: &Path,
+    plan: ExecutionPlan,
+    cli_args: Vec<String>,
+    mode: OutputMode,
+  ) -> Result<Option<Output>> {
+    // Check the arguments first, so a mistake in them doesn't run the dependencies
+    self
+      .run
+      .validate_args(plan.target(), cli_args.clone(), Some(runfile_dir))?;
+    // Phase 5: Run each dependency, then the command
+    let mut steps = plan.steps;
+    let target = steps.pop().expect("a plan always contains its target");
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    for step in steps {
+      if let Some(output) = self
+        .run
+        .run_in_directory(step.command, Vec::new(), mode, Some(runfile_dir))?
+      {
+        stdout.extend(output.stdout);
+        stderr.extend(output.stderr);
+      }
+    }
     let output = self
       .run
-      .run_in_directory(command, cli_args, OutputMode::Capture, Some(runfile_dir))?;
-    output.ok_or_else(|| anyhow::anyhow!("Expected output from capture mode"))
+      .run_in_directory(target.command, cli_args, mode, Some(runfile_dir))?;
+    Ok(output.map(|mut output| {
+      stdout.append(&mut output.stdout);
+      stderr.append(&mut output.stderr);
+      Output {
+        stdout,
+        stderr,
+        ..output
+      }
+    }))
   }
-  pub fn show_help(&self, colors: bool) -> Result<()> {
-    // Find and read Runfile
-    let runfile_path = self.find_runfile()?;
-    let content = fs::read_to_string(&runfile_path)?;
-    // Tokenize and parse
-    let tokens = self.tokenize.tokenize(&content)?;
-    let runfile = self.parse.parse(tokens)?;
+  /// Print the list of commands, including private ones if `all` is set
+  pub fn show_help(&self, colors: bool, all: bool) -> Result<()> {
+    let (_, runfile, _) = self.load()?;
     // Generate help output
-    runfile.generate_help_output(colors);
+    runfile.generate_help_output(colors, all);
     Ok(())
   }
+  /// Rewrite the Runfile in its canonical layout, or with `check`, fail if it isn't in it
+  pub fn format(&self, check: bool) -> Result<()> {
+    let (source, _, _) = self.load()?;
+    let formatted = format::format_tree(SyntaxTree::parse_with(&source.content, &self.tokenize))?;
+    if formatted == source.content {
+      return Ok(());
+    }
+    if check {
+      let line = source
+        .content
+        .lines()
+        .zip(formatted.lines())
+        .position(|(line, formatted)| line != formatted)
+        .unwrap_or_else(|| {
+          source
+            .content
+            .lines()
+            .count()
+            .min(formatted.lines().count())
+        })
+        + 1;
+      return Err(anyhow::anyhow!(
+        "{} is not formatted, starting at line {}\nRun `run --fmt` to format it",
+        source.display_path(),
+        line
+      ));
+    }
+    fs::write(&source.path, formatted)?;
+    Ok(())
+  }
+  /// Check every command of the Runfile, failing with all of the errors found and printing any warnings
+  pub fn validate(&self) -> Result<()> {
+    let source = self.read()?;
+    let (_, diagnostics) = self.validate_with_diagnostics(&source.content);
+    if diagnostics.has_errors() {
+      return Err(source.report_all(diagnostics).into());
+    }
+    if !diagnostics.is_empty() {
+      eprintln!("{}", source.report_all(diagnostics));
+    }
+    Ok(())
+  }
+  /// Check the Runfile for errors and likely mistakes, failing if there are any
+  ///
+  /// Problems are reported like errors in the Runfile, or with `json`, printed one JSON object per line.
+  pub fn check(&self, json: bool) -> Result<()> {
+    let source = self.read()?;
+    let (runfile, mut diagnostics) = self.validate_with_diagnostics(&source.content);
+    // Lints expect a Runfile that can be used as it is
+    if !diagnostics.has_errors() {
+      diagnostics.extend(lint::lint(
+        &runfile,
+        &SyntaxTree::parse_with(&source.content, &self.tokenize),
+      ));
+      diagnostics.sort();
+    }
+    if diagnostics.is_empty() {
+      return Ok(());
+    }
+    if !json {
+      return Err(source.report_all(diagnostics).into());
+    }
+    let path = source.display_path();
+    for diagnostic in diagnostics.iter() {
+      println!("{}", diagnostic.to_json(&path));
+    }
+    Err(anyhow::anyhow!(
+      "{} has {} problem{}",
+      path,
+      diagnostics.len(),
+      if diagnostics.len() == 1 { "" } else { "s" }
+    ))
+  }
+  /// Completion candidates for the last of `words`, the words typed after `run`
+  pub fn complete(&self, words: &[String]) -> Result<Vec<String>> {
+    let (_, runfile, _) = self.load()?;
+    Ok(runfile.complete(words))
+  }
+}
+
+/// The lines the Runfile's commands start on, in order
+fn command_starts(runfile: &Runfile) -> Vec<usize> {
+  let mut starts: Vec<usize> = runfile
+    .commands
+    .iter()
+    .map(|command| command.span.line)
+    .collect();
+  starts.sort();
+  starts
+}
+
+/// A Runfile's path and content
+struct Source {
+  path: PathBuf,
+  content: String,
+}
+
+impl Source {
+  /// Directory the Runfile's commands run in
+  fn dir(&self) -> &Path {
+    self.path.parent().unwrap_or(Path::new("."))
+  }
+  /// Turn an error that points into the Runfile into a report showing where
+  fn report(&self, error: anyhow::Error) -> anyhow::Error {
+    let diagnostics = match error.downcast::<Diagnostics>() {
+      Ok(diagnostics) => diagnostics,
+      Err(error) => match error.downcast::<Diagnostic>() {
+        Ok(diagnostic) => diagnostic.into(),
+        Err(error) => return error,
+      },
+    };
+    self.report_all(diagnostics).into()
+  }
+  /// Report of the diagnostics
+  fn report_all(&self, diagnostics: Diagnostics) -> Report {
+    Report {
+      path: self.display_path(),
+      source: self.content.clone(),
+      diagnostics,
+    }
+  }
+  /// The Runfile's path for messages, relative to the current directory when it's below it
+  fn display_path(&self) -> String {
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    let path = self.path.strip_prefix(&current_dir).unwrap_or(&self.path);
+    path.display().to_string()
+  }
 }
 
 #[cfg(test)]
@@ -123,6 +435,7 @@ mod tests {
 
     let pipeline = Pipeline::with_options(PipelineOptions {
       directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
     });
 
     // Create a temporary Runfile in the temp directory
@@ -140,6 +453,7 @@ mod tests {
 
     let pipeline = Pipeline::with_options(PipelineOptions {
       directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
     });
     let result = pipeline.find_runfile();
 
@@ -157,6 +471,7 @@ mod tests {
 
     let pipeline = Pipeline::with_options(PipelineOptions {
       directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
     });
     let result = pipeline.execute_command("test", vec![]);
 
@@ -189,6 +504,7 @@ mod tests {
 
     let pipeline = Pipeline::with_options(PipelineOptions {
       directory: Some(subdir.clone()),
+      ..Default::default()
     });
     let output = pipeline.execute_command("show", vec![]).unwrap();
     let stdout = String::from_utf8(output.stdout).unwrap();
@@ -197,4 +513,303 @@ mod tests {
 
     assert_eq!(actual_dir, expected_dir);
   }
+
+  #[test]
+  fn test_find_runfile_explicit_path() {
+    let temp_dir = TempDir::new().unwrap();
+    let runfile_path = temp_dir.path().join("ci.runfile");
+    fs::write(&runfile_path, "test:\n  echo \"Hello\"").unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      runfile: Some(runfile_path.clone()),
+      ..Default::default()
+    });
+
+    assert_eq!(pipeline.find_runfile().unwrap(), runfile_path);
+  }
+
+  #[test]
+  fn test_find_runfile_explicit_path_not_found() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      runfile: Some(temp_dir.path().join("missing.runfile")),
+      ..Default::default()
+    });
+    let result = pipeline.find_runfile();
+
+    assert!(result.is_err());
+    assert!(
+      result
+        .unwrap_err()
+        .to_string()
+        .contains("Runfile not found")
+    );
+  }
+
+  #[test]
+  fn test_execute_command_explicit_runfile_uses_its_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let tasks_dir = temp_dir.path().join("tasks");
+    fs::create_dir_all(&tasks_dir).unwrap();
+
+    let runfile_content = "show:\n  printf '%s\\n' \"$PWD\"\n";
+    fs::write(tasks_dir.join("release.runfile"), runfile_content).unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      directory: Some(temp_dir.path().to_path_buf()),
+      runfile: Some(tasks_dir.join("release.runfile")),
+      ..Default::default()
+    });
+    let output = pipeline.execute_command("show", vec![]).unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let expected_dir = tasks_dir.canonicalize().unwrap();
+    let actual_dir = std::path::PathBuf::from(stdout.trim()).canonicalize().unwrap();
+
+    assert_eq!(actual_dir, expected_dir);
+  }
+
+  #[test]
+  fn test_runfile_errors_show_where_they_are() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let runfile_content = "build:\n  cargo build\n\ntest --fast --fast:\n  cargo test\n";
+    fs::write(temp_dir.path().join("Runfile"), runfile_content).unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
+    });
+    let error = pipeline.execute_command("test", vec![]).unwrap_err();
+    let report = error.downcast_ref::<Report>().unwrap();
+    assert!(report.path.ends_with("Runfile"));
+    assert_eq!(
+      report.to_string(),
+      format!(
+        "{}:4:13: error: Duplicate flag name: fast\n  |\n4 | test --fast --fast:\n  |             ^^^^^^\n",
+        report.path
+      )
+    );
+    // Errors that aren't about the Runfile's content are left as they are
+    let error = pipeline.execute_command("tset", vec![]).unwrap_err();
+    assert!(error.downcast_ref::<Report>().is_none());
+  }
+
+  #[test]
+  fn test_runfile_errors_are_reported_together() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let runfile_content =
+      "@bogus\nbuild:\n  cargo build\n\nbuild, b:\n  cargo build --release\n\ntest # Tests:\n  cargo test\n";
+    fs::write(temp_dir.path().join("Runfile"), runfile_content).unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
+    });
+    let error = pipeline.execute_command("test", vec![]).unwrap_err();
+    let report = error.downcast_ref::<Report>().unwrap();
+    let lines: Vec<usize> = report
+      .diagnostics
+      .iter()
+      .map(|diagnostic| diagnostic.span.line)
+      .collect();
+    assert_eq!(lines, vec![1, 5, 8]);
+    assert!(
+      report
+        .to_string()
+        .contains(":5:1: error: Duplicate command name or alias: build\n")
+    );
+  }
+
+  #[test]
+  fn test_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let runfile_path = temp_dir.path().join("Runfile");
+    fs::write(
+      &runfile_path,
+      "build:\n    cargo build\ntest:\n    cargo test\n",
+    )
+    .unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
+    });
+    let error = pipeline.format(true).unwrap_err();
+    assert!(
+      error
+        .to_string()
+        .contains("is not formatted, starting at line 2")
+    );
+    pipeline.format(false).unwrap();
+    assert_eq!(
+      fs::read_to_string(&runfile_path).unwrap(),
+      "build:\n  cargo build\n\ntest:\n  cargo test\n"
+    );
+    pipeline.format(true).unwrap();
+
+    // Formatting could change what a Runfile read with the legacy grammar means
+    fs::write(
+      &runfile_path,
+      "@set legacy-grammar\n\nbuild:\n\tcargo build\n",
+    )
+    .unwrap();
+    assert!(pipeline.format(false).is_err());
+  }
+
+  #[test]
+  fn test_validate() {
+    let temp_dir = TempDir::new().unwrap();
+    let runfile_path = temp_dir.path().join("Runfile");
+    fs::write(
+      &runfile_path,
+      "build --fast --fast:\n  cargo build\n\ntest:\n\nlint:\n  echo lint\n",
+    )
+    .unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
+    });
+    // Running a command only checks that command
+    pipeline.execute_command("lint", vec![]).unwrap();
+    let error = pipeline.validate().unwrap_err();
+    let report = error.downcast_ref::<Report>().unwrap();
+    let lines: Vec<usize> = report
+      .diagnostics
+      .iter()
+      .map(|diagnostic| diagnostic.span.line)
+      .collect();
+    assert_eq!(lines, vec![1, 4]);
+
+    fs::write(&runfile_path, "build:\n  cargo build\n").unwrap();
+    pipeline.validate().unwrap();
+  }
+
+  #[test]
+  fn test_load_returns_warnings() {
+    let temp_dir = TempDir::new().unwrap();
+    let runfile_path = temp_dir.path().join("Runfile");
+    fs::write(
+      &runfile_path,
+      "build:\n  -r, --release   # Release\n  true $release\n",
+    )
+    .unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
+    });
+    assert_eq!(pipeline.complete(&[]).unwrap(), vec!["build".to_string()]);
+    let (_, _, warnings) = pipeline.load().unwrap();
+    let lines: Vec<usize> = warnings
+      .iter()
+      .map(|diagnostic| diagnostic.span.line)
+      .collect();
+    assert_eq!(lines, vec![2]);
+
+    fs::write(&runfile_path, "build:\n  true\n").unwrap();
+    assert!(pipeline.load().unwrap().2.is_empty());
+  }
+
+  #[test]
+  fn test_execute_command_refuses_old_parameters() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+      temp_dir.path().join("Runfile"),
+      "build:\n  --release\n  echo built\n\nmk:\n  make\n  echo made\n",
+    )
+    .unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
+    });
+    let error = pipeline.execute_command("build", vec![]).unwrap_err();
+    let report = error.downcast_ref::<Report>().unwrap();
+    let found: Vec<(usize, Option<&str>)> = report
+      .diagnostics
+      .iter()
+      .map(|diagnostic| (diagnostic.span.line, diagnostic.code))
+      .collect();
+    assert_eq!(found, vec![(2, Some("parameter-in-script"))]);
+    assert!(report.diagnostics.has_errors());
+    // A line that was an argument the script never used only gets a warning
+    let output = pipeline.execute_command("mk", vec![]).unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("made"));
+  }
+
+  #[test]
+  fn test_check() {
+    let temp_dir = TempDir::new().unwrap();
+    let runfile_path = temp_dir.path().join("Runfile");
+    fs::write(
+      &runfile_path,
+      "# Build the project
+build:
+  cargo build
+",
+    )
+    .unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
+    });
+    pipeline.check(false).unwrap();
+
+    fs::write(
+      &runfile_path,
+      "build --release:
+  cargo build
+",
+    )
+    .unwrap();
+    let error = pipeline.check(false).unwrap_err();
+    let report = error.downcast_ref::<Report>().unwrap();
+    let codes: Vec<_> = report
+      .diagnostics
+      .iter()
+      .map(|diagnostic| diagnostic.code)
+      .collect();
+    assert_eq!(
+      codes,
+      vec![Some("missing-description"), Some("unused-parameter")]
+    );
+    assert!(
+      pipeline
+        .check(true)
+        .unwrap_err()
+        .to_string()
+        .ends_with("Runfile has 2 problems")
+    );
+  }
+
+  #[test]
+  fn test_execute_command_runs_dependencies_once() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let runfile_content = "fmt:\n  printf fmt,\n\n@depends fmt\ncheck:\n  printf check,\n\n@depends fmt check\nverify target:\n  printf \"verify $target\"\n";
+    fs::write(temp_dir.path().join("Runfile"), runfile_content).unwrap();
+
+    let pipeline = Pipeline::with_options(PipelineOptions {
+      directory: Some(temp_dir.path().to_path_buf()),
+      ..Default::default()
+    });
+    let output = pipeline
+      .execute_command("verify", vec!["all".to_string()])
+      .unwrap();
+    assert_eq!(
+      String::from_utf8_lossy(&output.stdout),
+      "fmt,check,verify all"
+    );
+
+    // Arguments are checked before any dependency runs
+    let result = pipeline.execute_command("verify", vec![]);
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "Required argument 'target' not provided"
+    );
+  }
 }
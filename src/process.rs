@@ -0,0 +1,588 @@
+//! Supervision of script processes
+//!
+//! Scripts run in their own process group so that everything they start can be signaled together.
+//! While a script runs, SIGINT, SIGTERM and SIGHUP received by the runner are forwarded to the whole
+//! group. Once a signal has been forwarded, the group gets a grace period to exit before it is
+//! killed with SIGKILL. The runner's own handlers for those signals are put back once no script is
+//! running, and a signal that arrived meanwhile is then raised again, so the runner stops as it would
+//! have without a script.
+//!
+//! A script that owns the terminal and is stopped, as with Ctrl-Z, stops the runner too, so the shell
+//! gets the terminal back and sees a stopped job. When the job is continued, so is the script.
+
+use std::{
+  io::{self, BufRead, BufReader, Read},
+  process::{Command, ExitStatus, Output, Stdio},
+  sync::atomic::AtomicBool,
+  thread,
+  time::Duration,
+};
+
+/// Stream a line of output was written to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stream {
+  Stdout,
+  Stderr,
+}
+
+/// Run a command to completion with inherited stdio, returning its exit status
+pub(crate) fn status(cmd: &mut Command, grace_period: Duration) -> io::Result<ExitStatus> {
+  let child = Supervised::spawn(cmd, true)?;
+  child.wait(grace_period, None)
+}
+
+/// Run a command to completion, passing each line it writes to `on_line` as soon as it is complete
+///
+/// Setting `cancel` stops the command the same way a forwarded termination signal does.
+pub(crate) fn lines(
+  cmd: &mut Command,
+  grace_period: Duration,
+  cancel: &AtomicBool,
+  on_line: &(dyn Fn(Stream, &[u8]) + Sync),
+) -> io::Result<ExitStatus> {
+  cmd
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+  let mut child = Supervised::spawn(cmd, false)?;
+  let stdout = child.child.stdout.take();
+  let stderr = child.child.stderr.take();
+  thread::scope(|scope| {
+    let forward = |stream: Stream, reader: Box<dyn Read + Send>| {
+      scope.spawn(move || {
+        for line in BufReader::new(reader).split(b'\n') {
+          on_line(stream, &line?);
+        }
+        io::Result::Ok(())
+      })
+    };
+    let readers = [
+      stdout.map(|stdout| forward(Stream::Stdout, Box::new(stdout))),
+      stderr.map(|stderr| forward(Stream::Stderr, Box::new(stderr))),
+    ];
+    let status = child.wait(grace_period, Some(cancel))?;
+    for reader in readers.into_iter().flatten() {
+      reader
+        .join()
+        .map_err(|_| io::Error::other("Output reader thread panicked"))??;
+    }
+    Ok(status)
+  })
+}
+
+/// Run a command to completion, capturing its stdout and stderr
+pub(crate) fn output(cmd: &mut Command, grace_period: Duration) -> io::Result<Output> {
+  cmd
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+  let mut child = Supervised::spawn(cmd, false)?;
+  let stdout = child.child.stdout.take().map(read_to_end);
+  let stderr = child.child.stderr.take().map(read_to_end);
+  let status = child.wait(grace_period, None)?;
+  let join = |reader: Option<thread::JoinHandle<io::Result<Vec<u8>>>>| -> io::Result<Vec<u8>> {
+    match reader {
+      Some(reader) => reader
+        .join()
+        .map_err(|_| io::Error::other("Output reader thread panicked"))?,
+      None => Ok(Vec::new()),
+    }
+  };
+  Ok(Output {
+    status,
+    stdout: join(stdout)?,
+    stderr: join(stderr)?,
+  })
+}
+
+fn read_to_end<R: Read + Send + 'static>(mut reader: R) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+  thread::spawn(move || {
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    Ok(buffer)
+  })
+}
+
+#[cfg(unix)]
+use self::unix::Supervised;
+
+#[cfg(unix)]
+mod unix {
+  use std::{
+    io,
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Child, Command, ExitStatus},
+    sync::{
+      Mutex,
+      atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+  };
+
+  use libc::{SIGCONT, SIGHUP, SIGINT, SIGKILL, SIGTERM, SIGTSTP, SIGTTOU, STDIN_FILENO, c_int, pid_t};
+
+  /// How often the child and pending signals are polled
+  const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+  /// A child running in its own process group, with termination signals forwarded to it
+  pub(crate) struct Supervised {
+    pub(crate) child: Child,
+    pgid: pid_t,
+    signals: Forwarding,
+    /// Process group that owned the terminal before it was handed to the child
+    terminal_owner: Option<pid_t>,
+  }
+
+  impl Supervised {
+    /// Spawn the command as the leader of a new process group
+    ///
+    /// When `interactive` is set and the runner is in the foreground of a terminal, the terminal is
+    /// handed to the new group so the script can read from it and receives keyboard signals.
+    pub(crate) fn spawn(cmd: &mut Command, interactive: bool) -> io::Result<Self> {
+      // Register before spawning so a signal arriving mid-spawn is forwarded rather than fatal
+      let signals = Forwarding::install()?;
+      let terminal_owner = if interactive {
+        foreground_group()
+      } else {
+        None
+      };
+      cmd.process_group(0);
+      if terminal_owner.is_some() {
+        // SAFETY: the closure only calls async-signal-safe functions
+        unsafe {
+          cmd.pre_exec(|| {
+            give_terminal(libc::getpgrp());
+            Ok(())
+          });
+        }
+      }
+      let child = cmd.spawn()?;
+      let pgid = child.id() as pid_t;
+      if terminal_owner.is_some() {
+        // Also done in the child; whichever runs first wins the race against the script reading
+        give_terminal(pgid);
+      }
+      Ok(Self {
+        child,
+        pgid,
+        signals,
+        terminal_owner,
+      })
+    }
+    /// Wait for the child to exit, forwarding signals and cleaning up its process group
+    ///
+    /// Setting `cancel` sends SIGTERM to the group, as if it had been forwarded.
+    pub(crate) fn wait(mut self, grace_period: Duration, cancel: Option<&AtomicBool>) -> io::Result<ExitStatus> {
+      let mut deadline = None;
+      let status = loop {
+        if let Some(status) = self.try_wait()? {
+          break status;
+        }
+        for signal in self.signals.received() {
+          signal_group(self.pgid, signal);
+          deadline.get_or_insert_with(|| Instant::now() + grace_period);
+        }
+        if deadline.is_none() && cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+          signal_group(self.pgid, SIGTERM);
+          deadline = Some(Instant::now() + grace_period);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+          signal_group(self.pgid, SIGKILL);
+        }
+        thread::sleep(POLL_INTERVAL);
+      };
+      // If the script was interrupted, make sure nothing it started outlives it
+      if deadline.is_some() || status.signal().is_some() {
+        terminate_group(self.pgid, grace_period);
+      }
+      Ok(status)
+    }
+    /// Reap the child if it has exited, and follow it when it stops
+    ///
+    /// `Child::try_wait` doesn't report a stopped child, so this waits with `WUNTRACED` itself.
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+      let mut status = 0;
+      // SAFETY: waitpid only writes to the status it is given
+      let pid = unsafe { libc::waitpid(self.pgid, &mut status, libc::WNOHANG | libc::WUNTRACED) };
+      match pid {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(None),
+        _ if libc::WIFSTOPPED(status) => {
+          self.suspend();
+          Ok(None)
+        }
+        _ => Ok(Some(ExitStatus::from_raw(status))),
+      }
+    }
+    /// Stop the runner along with a stopped script that owns the terminal, then continue the script once
+    /// the runner is continued
+    fn suspend(&self) {
+      let Some(owner) = self.terminal_owner else {
+        return;
+      };
+      give_terminal(owner);
+      // SAFETY: raise has no memory safety requirements
+      unsafe {
+        // Returns once the shell continues the runner, with `fg` or `bg`
+        libc::raise(SIGTSTP);
+      }
+      // Continued in the background with `bg`, the shell keeps the terminal
+      if foreground_group() == Some(owner) {
+        give_terminal(self.pgid);
+      }
+      signal_group(self.pgid, SIGCONT);
+    }
+  }
+
+  impl Drop for Supervised {
+    fn drop(&mut self) {
+      if let Some(owner) = self.terminal_owner {
+        give_terminal(owner);
+      }
+    }
+  }
+
+  /// Signals forwarded to scripts
+  const FORWARDED: [c_int; 3] = [SIGINT, SIGTERM, SIGHUP];
+
+  /// How many times each of the forwarded signals has been received
+  static RECEIVED: [AtomicUsize; 3] = [const { AtomicUsize::new(0) }; 3];
+
+  /// The runner's own handlers, saved while any script is running
+  static HANDLERS: Mutex<Handlers> = Mutex::new(Handlers {
+    users: 0,
+    saved: Vec::new(),
+    received: [0; 3],
+  });
+
+  struct Handlers {
+    /// Number of live [`Forwarding`]s
+    users: usize,
+    saved: Vec<libc::sigaction>,
+    /// `RECEIVED` when the handlers were installed
+    received: [usize; 3],
+  }
+
+  extern "C" fn record(signal: c_int) {
+    if let Some(index) = FORWARDED.iter().position(|&forwarded| forwarded == signal) {
+      RECEIVED[index].fetch_add(1, Ordering::SeqCst);
+    }
+  }
+
+  fn received_counts() -> [usize; 3] {
+    std::array::from_fn(|index| RECEIVED[index].load(Ordering::SeqCst))
+  }
+
+  /// Records the forwarded signals for one script, while keeping the runner's handlers aside
+  ///
+  /// Scripts running at the same time share the installed handlers; the last one to finish puts the
+  /// runner's handlers back.
+  struct Forwarding {
+    /// `RECEIVED` when the signals were last checked
+    seen: [usize; 3],
+  }
+
+  impl Forwarding {
+    fn install() -> io::Result<Self> {
+      let mut handlers = HANDLERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+      if handlers.users == 0 {
+        let mut saved = Vec::new();
+        for signal in FORWARDED {
+          // SAFETY: the action is fully initialized, and `record` only touches atomics
+          unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = record as extern "C" fn(c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(signal, &action, &mut previous) != 0 {
+              let error = io::Error::last_os_error();
+              restore(&saved);
+              return Err(error);
+            }
+            saved.push(previous);
+          }
+        }
+        handlers.saved = saved;
+        handlers.received = received_counts();
+      }
+      handlers.users += 1;
+      Ok(Self {
+        seen: received_counts(),
+      })
+    }
+    /// Signals received since the last call
+    fn received(&mut self) -> Vec<c_int> {
+      let counts = received_counts();
+      let signals = FORWARDED
+        .iter()
+        .zip(counts.iter().zip(self.seen))
+        .filter(|(_, (count, seen))| **count > *seen)
+        .map(|(&signal, _)| signal)
+        .collect();
+      self.seen = counts;
+      signals
+    }
+  }
+
+  impl Drop for Forwarding {
+    fn drop(&mut self) {
+      let mut handlers = HANDLERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+      handlers.users -= 1;
+      if handlers.users > 0 {
+        return;
+      }
+      restore(&handlers.saved);
+      let counts = received_counts();
+      for (index, &signal) in FORWARDED.iter().enumerate() {
+        if counts[index] > handlers.received[index] {
+          // The signal was meant for the runner as well as the script
+          // SAFETY: raise has no memory safety requirements
+          unsafe {
+            libc::raise(signal);
+          }
+        }
+      }
+    }
+  }
+
+  /// Put back the saved handlers of the forwarded signals
+  fn restore(saved: &[libc::sigaction]) {
+    for (&signal, action) in FORWARDED.iter().zip(saved) {
+      // SAFETY: the action was filled in by sigaction
+      unsafe {
+        libc::sigaction(signal, action, std::ptr::null_mut());
+      }
+    }
+  }
+
+  /// Whether the runner's handlers are in place, with no script running
+  #[cfg(test)]
+  pub(crate) fn handlers_restored() -> Option<bool> {
+    let handlers = HANDLERS
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if handlers.users > 0 {
+      return None;
+    }
+    let restored = FORWARDED.iter().all(|&signal| {
+      // SAFETY: sigaction only writes to the action it is given
+      unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        libc::sigaction(signal, std::ptr::null(), &mut action);
+        action.sa_sigaction != record as extern "C" fn(c_int) as libc::sighandler_t
+      }
+    });
+    Some(restored)
+  }
+
+  /// Send SIGTERM to every process left in the group, then SIGKILL once the grace period expires
+  pub(crate) fn terminate_group(pgid: pid_t, grace_period: Duration) {
+    if !signal_group(pgid, SIGTERM) {
+      return;
+    }
+    let deadline = Instant::now() + grace_period;
+    while Instant::now() < deadline {
+      if !signal_group(pgid, 0) {
+        return;
+      }
+      thread::sleep(POLL_INTERVAL);
+    }
+    signal_group(pgid, SIGKILL);
+  }
+
+  /// Signal a process group, returning whether any process in it still exists
+  fn signal_group(pgid: pid_t, signal: i32) -> bool {
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(-pgid, signal) == 0 }
+  }
+
+  /// Process group of the runner, if it is currently in the foreground of the terminal on stdin
+  fn foreground_group() -> Option<pid_t> {
+    // SAFETY: these calls only query process and terminal state
+    unsafe {
+      let pgrp = libc::getpgrp();
+      (libc::isatty(STDIN_FILENO) == 1 && libc::tcgetpgrp(STDIN_FILENO) == pgrp).then_some(pgrp)
+    }
+  }
+
+  /// Make `pgid` the foreground process group of the terminal on stdin
+  ///
+  /// SIGTTOU is ignored for the call because the caller may itself be in a background group.
+  fn give_terminal(pgid: pid_t) {
+    // SAFETY: signal and tcsetpgrp are async-signal-safe and have no memory safety requirements
+    unsafe {
+      let previous = libc::signal(SIGTTOU, libc::SIG_IGN);
+      libc::tcsetpgrp(STDIN_FILENO, pgid);
+      libc::signal(SIGTTOU, previous);
+    }
+  }
+}
+
+#[cfg(not(unix))]
+use self::fallback::Supervised;
+
+#[cfg(not(unix))]
+mod fallback {
+  use std::{
+    io,
+    process::{Child, Command, ExitStatus},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+  };
+
+  /// A child waited on directly; process groups and signal forwarding are unix-only
+  pub(crate) struct Supervised {
+    pub(crate) child: Child,
+  }
+
+  impl Supervised {
+    pub(crate) fn spawn(cmd: &mut Command, _interactive: bool) -> io::Result<Self> {
+      Ok(Self {
+        child: cmd.spawn()?,
+      })
+    }
+    pub(crate) fn wait(mut self, _grace_period: Duration, cancel: Option<&AtomicBool>) -> io::Result<ExitStatus> {
+      let Some(cancel) = cancel else {
+        return self.child.wait();
+      };
+      loop {
+        if let Some(status) = self.child.try_wait()? {
+          return Ok(status);
+        }
+        if cancel.load(Ordering::Relaxed) {
+          self.child.kill()?;
+          return self.child.wait();
+        }
+        thread::sleep(Duration::from_millis(10));
+      }
+    }
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use std::{
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::Command,
+    sync::atomic::AtomicBool,
+    time::Duration,
+  };
+
+  use super::*;
+
+  #[test]
+  fn test_output_captures_stdout_and_stderr() {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("echo out; echo err >&2; exit 3");
+    let output = output(&mut cmd, Duration::from_secs(1)).unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "out\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "err\n");
+  }
+
+  #[test]
+  fn test_lines_passes_each_line() {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("echo one; echo err >&2; printf two");
+    let lines = std::sync::Mutex::new(Vec::new());
+    let status = super::lines(
+      &mut cmd,
+      Duration::from_secs(1),
+      &AtomicBool::new(false),
+      &|stream, line| {
+        lines
+          .lock()
+          .unwrap()
+          .push((stream, String::from_utf8_lossy(line).to_string()))
+      },
+    )
+    .unwrap();
+
+    assert!(status.success());
+    let mut lines = lines.into_inner().unwrap();
+    lines.sort_by_key(|(stream, _)| *stream == Stream::Stderr);
+    assert_eq!(
+      lines,
+      vec![
+        (Stream::Stdout, "one".to_string()),
+        (Stream::Stdout, "two".to_string()),
+        (Stream::Stderr, "err".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_lines_stops_when_cancelled() {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("sleep 30");
+    let cancel = AtomicBool::new(true);
+    let status = super::lines(&mut cmd, Duration::from_secs(1), &cancel, &|_, _| {}).unwrap();
+
+    assert_eq!(status.signal(), Some(libc::SIGTERM));
+  }
+
+  #[test]
+  fn test_script_runs_in_own_process_group() {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("ps -o pgid= -p $$ | tr -d ' '; echo $$");
+    let output = output(&mut cmd, Duration::from_secs(1)).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    // The script's shell is the leader of its own group
+    assert_eq!(lines.len(), 2, "unexpected output: {}", stdout);
+    assert_eq!(lines[0], lines[1]);
+  }
+
+  #[test]
+  fn test_handlers_restored_after_script() {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg("exit 0");
+    assert!(status(&mut cmd, Duration::from_secs(1)).unwrap().success());
+
+    // Other tests may be running scripts at the same time, which keeps the handlers installed
+    if let Some(restored) = unix::handlers_restored() {
+      assert!(restored);
+    }
+  }
+
+  #[test]
+  fn test_terminate_group_kills_after_grace_period() {
+    let mut child = Command::new("sh")
+      .arg("-c")
+      .arg("trap '' TERM; sleep 30")
+      .process_group(0)
+      .spawn()
+      .unwrap();
+
+    // Give the shell time to install its trap before signaling
+    std::thread::sleep(Duration::from_millis(100));
+    unix::terminate_group(child.id() as libc::pid_t, Duration::from_millis(100));
+    let status = child.wait().unwrap();
+
+    assert_eq!(status.signal(), Some(libc::SIGKILL));
+  }
+
+  #[test]
+  fn test_terminate_group_sends_sigterm_first() {
+    let mut child = Command::new("sh")
+      .arg("-c")
+      .arg("sleep 30")
+      .process_group(0)
+      .spawn()
+      .unwrap();
+
+    unix::terminate_group(child.id() as libc::pid_t, Duration::from_millis(100));
+    let status = child.wait().unwrap();
+
+    assert_eq!(status.signal(), Some(libc::SIGTERM));
+  }
+}
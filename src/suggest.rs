@@ -0,0 +1,71 @@
+//! "Did you mean" suggestions for mistyped command and flag names
+//!
+//! Names are compared by edit distance, counting a swap of two neighbouring characters as a single
+//! edit, so `biuld` is one edit away from `build`.
+
+/// The candidate closest to `name`, if any is close enough to be a likely typo
+///
+/// Up to a third of the name's characters may differ, with at least one allowed. Ties go to the
+/// candidate that comes first.
+pub(crate) fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+  let max_distance = (name.chars().count() / 3).max(1);
+  let mut best: Option<(usize, &str)> = None;
+  for candidate in candidates {
+    let distance = distance(name, candidate);
+    if distance <= max_distance && best.is_none_or(|(best_distance, _)| distance < best_distance) {
+      best = Some((distance, candidate));
+    }
+  }
+  best.map(|(_, candidate)| candidate)
+}
+
+/// Number of insertions, deletions, substitutions and swaps of neighbouring characters that turn `a`
+/// into `b`
+fn distance(a: &str, b: &str) -> usize {
+  let a: Vec<char> = a.chars().collect();
+  let b: Vec<char> = b.chars().collect();
+  // rows[i][j] is the distance between the first i characters of a and the first j of b
+  let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+  for (i, row) in rows.iter_mut().enumerate() {
+    row[0] = i;
+  }
+  rows[0] = (0..=b.len()).collect();
+  for i in 1..=a.len() {
+    for j in 1..=b.len() {
+      let cost = usize::from(a[i - 1] != b[j - 1]);
+      rows[i][j] = (rows[i - 1][j] + 1)
+        .min(rows[i][j - 1] + 1)
+        .min(rows[i - 1][j - 1] + cost);
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        rows[i][j] = rows[i][j].min(rows[i - 2][j - 2] + 1);
+      }
+    }
+  }
+  rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_distance() {
+    assert_eq!(distance("build", "build"), 0);
+    assert_eq!(distance("biuld", "build"), 1);
+    assert_eq!(distance("relase", "release"), 1);
+    assert_eq!(distance("tset", "test"), 1);
+    assert_eq!(distance("", "abc"), 3);
+    assert_eq!(distance("deploy", "build"), 5);
+  }
+
+  #[test]
+  fn test_closest() {
+    let candidates = ["build", "b", "test", "deploy"];
+    assert_eq!(closest("biuld", candidates), Some("build"));
+    assert_eq!(closest("tst", candidates), Some("test"));
+    assert_eq!(closest("deplyo", candidates), Some("deploy"));
+    assert_eq!(closest("lint", candidates), None);
+    // One edit is always allowed, even for short names
+    assert_eq!(closest("c", candidates), Some("b"));
+  }
+}
@@ -30,10 +30,8 @@ fn test_all_sample_help_outputs() {
       let expected_output = fs::read_to_string(&expected_file).expect("Could not read expected file");
 
       // Parse the runfile and generate help output
-      let runfile = run::parse_runfile(&runfile_content).expect(&format!(
-        "Failed to parse runfile for sample {:?}",
-        file_stem
-      ));
+      let runfile = run::parse_runfile(&runfile_content)
+        .unwrap_or_else(|_| panic!("Failed to parse runfile for sample {:?}", file_stem));
 
       let actual_output = runfile.generate_help(false);
 
@@ -47,3 +45,43 @@ fn test_all_sample_help_outputs() {
     }
   }
 }
+
+#[test]
+fn test_formatting_samples_keeps_their_meaning() {
+  for entry in fs::read_dir("./tests/samples").expect("Could not read samples directory") {
+    let path = entry.expect("Invalid entry in samples directory").path();
+    if path.extension().and_then(|e| e.to_str()) != Some("runfile") {
+      continue;
+    }
+    let runfile_content = fs::read_to_string(&path).expect("Could not read sample file");
+    let formatted = run::format::format(&runfile_content).unwrap();
+    assert_eq!(
+      run::format::format(&formatted).unwrap(),
+      formatted,
+      "Formatting isn't stable for sample {:?}",
+      path
+    );
+
+    let help = |content: &str| run::parse_runfile(content).unwrap().generate_help(false);
+    assert_eq!(
+      help(&formatted),
+      help(&runfile_content),
+      "Formatting changed sample {:?}",
+      path
+    );
+    let scripts = |content: &str| -> Vec<String> {
+      run::parse_runfile(content)
+        .unwrap()
+        .commands
+        .into_iter()
+        .map(|command| command.script)
+        .collect()
+    };
+    assert_eq!(
+      scripts(&formatted),
+      scripts(&runfile_content),
+      "Formatting changed a script in sample {:?}",
+      path
+    );
+  }
+}